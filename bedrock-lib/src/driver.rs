@@ -1,10 +1,22 @@
 use std::sync::OnceLock;
 
-use wgpu::{Adapter, Device, Instance, Queue, Surface, TextureFormat, TextureView};
+use wgpu::{
+    Adapter, Device, Features, Instance, Queue, Surface, TextureFormat, TextureFormatFeatures,
+    TextureView,
+};
 use winit::window::Window;
 
 pub static SWAP_CHAIN_FORMAT: OnceLock<TextureFormat> = OnceLock::new();
 
+/// Requested whenever the adapter has them. Adapter specific format features let storage
+/// textures use everything the adapter supports, see `format_features`; the rest back the
+/// `polygon_mode` and `unclipped_depth` shader options and `#[push_constants]`.
+const OPTIONAL_FEATURES: Features = Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
+    .union(Features::PUSH_CONSTANTS)
+    .union(Features::POLYGON_MODE_LINE)
+    .union(Features::POLYGON_MODE_POINT)
+    .union(Features::DEPTH_CLIP_CONTROL);
+
 pub struct Driver {
     pub instance: Instance,
     pub surface: Surface,
@@ -30,9 +42,12 @@ impl Driver {
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
-                    features: wgpu::Features::empty(),
-                    limits: wgpu::Limits::downlevel_webgl2_defaults()
-                        .using_resolution(adapter.limits()),
+                    features: adapter.features() & OPTIONAL_FEATURES,
+                    limits: wgpu::Limits {
+                        // Shaders check their own push constant size against this.
+                        max_push_constant_size: adapter.limits().max_push_constant_size,
+                        ..base_limits().using_resolution(adapter.limits())
+                    },
                     label: None,
                 },
                 None,
@@ -41,9 +56,7 @@ impl Driver {
             .expect("Failed to create device");
 
         let swapchain_capabilities = surface.get_capabilities(&adapter);
-        let swapchain_format = SWAP_CHAIN_FORMAT
-            .get_or_init(|| swapchain_capabilities.formats[0])
-            .clone();
+        let swapchain_format = *SWAP_CHAIN_FORMAT.get_or_init(|| swapchain_capabilities.formats[0]);
 
         println!("SWAPCHAIN FORMAT: {:?}", swapchain_format);
 
@@ -68,6 +81,25 @@ impl Driver {
         }
     }
 
+    /// The capabilities the device validates `format` against: the adapter's own with
+    /// `TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES` or on downlevel backends, WebGPU's
+    /// guarantees otherwise.
+    pub fn format_features(&self, format: TextureFormat) -> TextureFormatFeatures {
+        let adapter_specific = self
+            .device
+            .features()
+            .contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+            || !self
+                .adapter
+                .get_downlevel_capabilities()
+                .is_webgpu_compliant();
+        if adapter_specific {
+            self.adapter.get_texture_format_features(format)
+        } else {
+            format.guaranteed_format_features(self.device.features())
+        }
+    }
+
     pub fn swap_chain_view(&self) -> TextureView {
         self.surface
             .get_current_texture()
@@ -76,3 +108,12 @@ impl Driver {
             .create_view(&wgpu::TextureViewDescriptor::default())
     }
 }
+
+/// WebGL2 has no storage buffers, everywhere else the downlevel defaults provide them.
+fn base_limits() -> wgpu::Limits {
+    if cfg!(target_arch = "wasm32") {
+        wgpu::Limits::downlevel_webgl2_defaults()
+    } else {
+        wgpu::Limits::downlevel_defaults()
+    }
+}
@@ -1,7 +1,19 @@
+use std::marker::PhantomData;
+
+use encase::{internal::WriteInto, ShaderSize, ShaderType, StorageBuffer, UniformBuffer};
 use wgpu::{
-    BindGroupLayoutEntry, Buffer, BufferDescriptor, BufferUsages, Device, Extent3d, Texture,
-    TextureDescriptor, TextureDimension, TextureUsages, TextureView,
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroupLayoutEntry, Buffer, BufferBinding, BufferDescriptor, BufferSize, BufferUsages,
+    Device, Extent3d, IndexFormat, Queue, Sampler, SamplerDescriptor, StorageTextureAccess,
+    SurfaceTexture, Texture, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureFormatFeatureFlags, TextureUsages, TextureView, TextureViewDimension, VertexAttribute,
 };
+use winit::window::Window;
+
+pub use bedrock_preprocess as preprocess;
+pub use bytemuck;
+pub use encase;
+pub use winit;
 
 pub mod driver;
 pub mod runtime;
@@ -15,43 +27,263 @@ pub trait TextureProvider {
     const LAYOUT_ENTRY: BindGroupLayoutEntry;
     const WIDTH: u32;
     const HEIGHT: u32;
-    const ROW_SIZE: u32 = (((Self::WIDTH * 4) + 255) / 256) * 256;
-    const BUFFER_SIZE: u32 = Self::ROW_SIZE * Self::HEIGHT;
+    /// The depth of 3D textures, the layer count of arrays and 6 for cubes.
+    const DEPTH_OR_ARRAY_LAYERS: u32 = 1;
+    const DIMENSION: TextureDimension = TextureDimension::D2;
+    const VIEW_DIMENSION: TextureViewDimension = TextureViewDimension::D2;
+    /// `None` uses the swapchain format, which needs a `Driver` to exist first.
+    const FORMAT: Option<TextureFormat> = None;
+    const BYTES_PER_TEXEL: u32 = 4;
+    const ROW_SIZE: u32 = (Self::WIDTH * Self::BYTES_PER_TEXEL).div_ceil(256) * 256;
+    const BUFFER_SIZE: u32 = Self::ROW_SIZE * Self::HEIGHT * Self::DEPTH_OR_ARRAY_LAYERS;
+    /// Whether the layout allows sampling with a filtering sampler.
+    const FILTERABLE: bool = false;
+    /// Set for storage textures, which also get `STORAGE_BINDING` usage.
+    const STORAGE_ACCESS: Option<StorageTextureAccess> = None;
+
+    fn format() -> TextureFormat {
+        Self::FORMAT.unwrap_or_else(|| {
+            *driver::SWAP_CHAIN_FORMAT
+                .get()
+                .expect("Texture uses the swapchain format before a Driver was created")
+        })
+    }
 
     fn texture(device: &Device) -> ShaderTexture {
+        let format = Self::format();
+        let format_features = format.guaranteed_format_features(device.features());
+        assert!(
+            !Self::FILTERABLE
+                || format_features
+                    .flags
+                    .contains(TextureFormatFeatureFlags::FILTERABLE),
+            "Texture is declared filterable but {:?} cannot be filtered",
+            format
+        );
         let texture = device.create_texture(&TextureDescriptor {
             label: None,
-            view_formats: &[*driver::SWAP_CHAIN_FORMAT.get().unwrap()],
+            view_formats: &[format],
             size: Extent3d {
                 width: Self::WIDTH,
                 height: Self::HEIGHT,
-                depth_or_array_layers: 1,
+                depth_or_array_layers: Self::DEPTH_OR_ARRAY_LAYERS,
             },
             mip_level_count: 1,
             sample_count: 1,
-            dimension: TextureDimension::D2,
-            format: *driver::SWAP_CHAIN_FORMAT.get().unwrap(),
-            usage: TextureUsages::TEXTURE_BINDING
-                | TextureUsages::RENDER_ATTACHMENT
-                | TextureUsages::COPY_DST
-                | TextureUsages::COPY_SRC,
+            dimension: Self::DIMENSION,
+            format,
+            usage: Self::usage(format_features.allowed_usages),
         });
 
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(Self::VIEW_DIMENSION),
+            ..Default::default()
+        });
+
+        ShaderTexture { texture, view }
+    }
+
+    /// Like `texture`, but first checks that the adapter supports the format with
+    /// `STORAGE_ACCESS`, which WebGPU only guarantees for a few formats and `write_only`.
+    fn storage_texture(driver: &driver::Driver) -> ShaderTexture {
+        if let Some(access) = Self::STORAGE_ACCESS {
+            let format = Self::format();
+            let format_features = driver.format_features(format);
+            assert!(
+                format_features
+                    .allowed_usages
+                    .contains(TextureUsages::STORAGE_BINDING),
+                "{:?} cannot be used as a storage texture on this adapter",
+                format
+            );
+            assert!(
+                access == StorageTextureAccess::WriteOnly
+                    || format_features
+                        .flags
+                        .contains(TextureFormatFeatureFlags::STORAGE_READ_WRITE),
+                "{:?} storage textures only support write_only access on this adapter",
+                format
+            );
+        }
+        Self::texture(&driver.device)
+    }
+
+    fn usage(allowed_usages: TextureUsages) -> TextureUsages {
+        let mut usage =
+            TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST | TextureUsages::COPY_SRC;
+        // 3D textures can't be render attachments.
+        if Self::DIMENSION == TextureDimension::D2 {
+            usage |= TextureUsages::RENDER_ATTACHMENT;
+        }
+        // Not every format can be rendered to, e.g. `Rgb9e5Ufloat`.
+        let usage = usage & allowed_usages;
+        match Self::STORAGE_ACCESS {
+            Some(_) => usage | TextureUsages::STORAGE_BINDING,
+            None => usage,
+        }
+    }
+}
+
+/// A view `draw_encoder` renders an `#[output]` to, with the format it is checked against.
+pub trait OutputTarget {
+    fn view(&self) -> &TextureView;
+    fn format(&self) -> TextureFormat;
+
+    fn sample_count(&self) -> u32 {
+        1
+    }
+}
+
+impl OutputTarget for ShaderTexture {
+    fn view(&self) -> &TextureView {
+        &self.view
+    }
+
+    fn format(&self) -> TextureFormat {
+        self.texture.format()
+    }
+
+    fn sample_count(&self) -> u32 {
+        self.texture.sample_count()
+    }
+}
+
+impl ShaderTexture {
+    /// A multisampled render target to resolve into a single sampled `#[output]`, checking
+    /// that the adapter supports `sample_count` for `format`.
+    pub fn multisampled(
+        driver: &driver::Driver,
+        width: u32,
+        height: u32,
+        format: TextureFormat,
+        sample_count: u32,
+    ) -> Self {
+        assert!(
+            driver
+                .format_features(format)
+                .flags
+                .sample_count_supported(sample_count),
+            "{:?} does not support {}x multisampling on this adapter",
+            format,
+            sample_count
+        );
+        let texture = driver.device.create_texture(&TextureDescriptor {
+            label: None,
+            view_formats: &[format],
+            size: Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: TextureDimension::D2,
+            format,
+            usage: TextureUsages::RENDER_ATTACHMENT,
+        });
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
 
         ShaderTexture { texture, view }
     }
 }
 
-pub struct ShaderUniform {
+/// A depth (and stencil) attachment covering a whole window.
+pub struct DepthTexture {
+    pub texture: Texture,
+    pub view: TextureView,
+}
+
+impl DepthTexture {
+    /// Sized to `window`'s inner size, recreate it when the window resizes.
+    pub fn new(device: &Device, window: &Window, format: TextureFormat, sample_count: u32) -> Self {
+        let size = window.inner_size();
+        let texture = device.create_texture(&TextureDescriptor {
+            label: None,
+            view_formats: &[format],
+            size: Extent3d {
+                width: size.width.max(1),
+                height: size.height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: TextureDimension::D2,
+            format,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Self { texture, view }
+    }
+
+    pub fn format(&self) -> TextureFormat {
+        self.texture.format()
+    }
+}
+
+/// A view of the swapchain texture acquired for the current frame.
+pub struct SurfaceView {
+    pub view: TextureView,
+    format: TextureFormat,
+}
+
+impl SurfaceView {
+    pub fn new(frame: &SurfaceTexture) -> Self {
+        Self {
+            view: frame
+                .texture
+                .create_view(&wgpu::TextureViewDescriptor::default()),
+            format: frame.texture.format(),
+        }
+    }
+}
+
+impl OutputTarget for SurfaceView {
+    fn view(&self) -> &TextureView {
+        &self.view
+    }
+
+    fn format(&self) -> TextureFormat {
+        self.format
+    }
+}
+
+pub struct ShaderSampler {
+    pub sampler: Sampler,
+}
+
+pub trait SamplerProvider {
+    const LAYOUT_ENTRY: BindGroupLayoutEntry;
+    const DESCRIPTOR: SamplerDescriptor<'static>;
+
+    fn sampler(device: &Device) -> ShaderSampler {
+        ShaderSampler {
+            sampler: device.create_sampler(&Self::DESCRIPTOR),
+        }
+    }
+}
+
+/// A uniform buffer holding a `T`. Typed uniforms (`T: ShaderType`) can be written directly,
+/// untyped ones (`#[uniform(size = ..)]`) are written through `buffer`.
+pub struct ShaderUniform<T> {
     pub buffer: Buffer,
+    marker: PhantomData<T>,
 }
 
-pub trait UniformProvider {
+impl<T: ShaderType + WriteInto> ShaderUniform<T> {
+    /// Writes `value` using the WGSL uniform address space layout.
+    pub fn write(&self, queue: &Queue, value: &T) {
+        let mut bytes = UniformBuffer::new(Vec::<u8>::new());
+        bytes.write(value).expect("Failed to encode uniform value");
+        queue.write_buffer(&self.buffer, 0, &bytes.into_inner());
+    }
+}
+
+pub trait UniformProvider: Sized {
     const LAYOUT_ENTRY: BindGroupLayoutEntry;
     const SIZE: u64;
 
-    fn uniform(device: &Device) -> ShaderUniform {
+    fn uniform(device: &Device) -> ShaderUniform<Self> {
         ShaderUniform {
             buffer: device.create_buffer(&BufferDescriptor {
                 label: None,
@@ -59,6 +291,351 @@ pub trait UniformProvider {
                 usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
                 mapped_at_creation: false,
             }),
+            marker: PhantomData,
+        }
+    }
+
+    fn uniform_ring(device: &Device, capacity: u32) -> UniformRing<Self> {
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as u64;
+        let stride = Self::SIZE.div_ceil(alignment) * alignment;
+        UniformRing {
+            buffer: device.create_buffer(&BufferDescriptor {
+                label: None,
+                size: stride * capacity.max(1) as u64,
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            stride,
+            capacity,
+            len: 0,
+            marker: PhantomData,
+        }
+    }
+}
+
+/// Many values of a `#[uniform(dynamic)]` entry packed into one buffer, each at a multiple of
+/// `min_uniform_buffer_offset_alignment`. Values pushed during a frame are bound by passing the
+/// returned offsets to the generated draw function; `clear` starts the next frame.
+pub struct UniformRing<T> {
+    pub buffer: Buffer,
+    pub stride: u64,
+    pub capacity: u32,
+    pub len: u32,
+    marker: PhantomData<T>,
+}
+
+impl<T: UniformProvider> UniformRing<T> {
+    /// Writes `bytes` into the next slot and returns its dynamic offset.
+    pub fn push_bytes(&mut self, queue: &Queue, bytes: &[u8]) -> u32 {
+        assert!(
+            self.len < self.capacity,
+            "UniformRing is full ({} values)",
+            self.capacity
+        );
+        assert!(bytes.len() as u64 <= T::SIZE, "Uniform value is too large");
+        let offset = self.len as u64 * self.stride;
+        queue.write_buffer(&self.buffer, offset, bytes);
+        self.len += 1;
+        offset as u32
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// One slot of the ring; the offset is supplied when the group is bound.
+    pub fn binding(&self) -> BufferBinding<'_> {
+        BufferBinding {
+            buffer: &self.buffer,
+            offset: 0,
+            size: BufferSize::new(T::SIZE),
+        }
+    }
+}
+
+impl<T: UniformProvider + ShaderType + WriteInto> UniformRing<T> {
+    /// Writes `value` using the WGSL uniform address space layout and returns its dynamic offset.
+    pub fn push(&mut self, queue: &Queue, value: &T) -> u32 {
+        let mut bytes = UniformBuffer::new(Vec::<u8>::new());
+        bytes.write(value).expect("Failed to encode uniform value");
+        self.push_bytes(queue, &bytes.into_inner())
+    }
+}
+
+/// A storage buffer holding a WGSL array of `len` elements of type `T`.
+pub struct ShaderStorage<T> {
+    pub buffer: Buffer,
+    pub len: u64,
+    marker: PhantomData<T>,
+}
+
+impl<T: ShaderType + ShaderSize + WriteInto> ShaderStorage<T> {
+    /// Writes `elements` to the start of the array using the WGSL storage address space layout.
+    pub fn write(&self, queue: &Queue, elements: &[T]) {
+        assert!(
+            elements.len() as u64 <= self.len,
+            "Cannot write {} elements to a storage buffer of {}",
+            elements.len(),
+            self.len
+        );
+        let mut bytes = StorageBuffer::new(Vec::<u8>::new());
+        bytes
+            .write(elements)
+            .expect("Failed to encode storage elements");
+        queue.write_buffer(&self.buffer, 0, &bytes.into_inner());
+    }
+}
+
+pub trait StorageProvider: ShaderType + ShaderSize + Sized {
+    const LAYOUT_ENTRY: BindGroupLayoutEntry;
+
+    /// Size of one array element including WGSL array stride padding.
+    fn stride() -> u64 {
+        <[Self] as ShaderType>::min_size().get()
+    }
+
+    /// Allocates a runtime-sized array of `len` elements.
+    fn storage(device: &Device, len: u64) -> ShaderStorage<Self> {
+        ShaderStorage {
+            buffer: device.create_buffer(&BufferDescriptor {
+                label: None,
+                size: Self::stride() * len.max(1),
+                usage: BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }),
+            len,
+            marker: PhantomData,
         }
     }
 }
+
+/// A vertex buffer of `len` vertices of type `T`.
+pub struct VertexBuffer<T> {
+    pub buffer: Buffer,
+    pub len: u32,
+    marker: PhantomData<T>,
+}
+
+impl<T: bytemuck::Pod> VertexBuffer<T> {
+    /// Writes `vertices` to the start of the buffer.
+    pub fn write(&self, queue: &Queue, vertices: &[T]) {
+        assert!(
+            vertices.len() as u32 <= self.len,
+            "Cannot write {} vertices to a vertex buffer of {}",
+            vertices.len(),
+            self.len
+        );
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(vertices));
+    }
+}
+
+/// A per-instance vertex buffer that can be written every frame but only uploads instances that
+/// changed. The buffer only grows, to the next power of two when the instances don't fit.
+pub struct InstanceBuffer<T> {
+    pub buffer: Buffer,
+    pub len: u32,
+    capacity: u32,
+    /// The instances last uploaded, compared against to skip unchanged writes.
+    uploaded: Vec<T>,
+}
+
+impl<T: bytemuck::Pod> InstanceBuffer<T> {
+    /// Replaces the buffer contents with `instances`, reallocating when they don't fit. Returns
+    /// whether anything was uploaded, which is skipped when `instances` match the last write.
+    pub fn write(&mut self, device: &Device, queue: &Queue, instances: &[T]) -> bool {
+        let len = instances.len() as u32;
+        if len > self.capacity {
+            *self = Self::with_capacity(device, len.next_power_of_two());
+        } else if bytemuck::cast_slice::<T, u8>(instances) == bytemuck::cast_slice(&self.uploaded) {
+            return false;
+        }
+        self.len = len;
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(instances));
+        self.uploaded.clear();
+        self.uploaded.extend_from_slice(instances);
+        true
+    }
+
+    fn with_capacity(device: &Device, capacity: u32) -> Self {
+        Self {
+            buffer: device.create_buffer(&BufferDescriptor {
+                label: None,
+                size: std::mem::size_of::<T>() as u64 * capacity.max(1) as u64,
+                usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            len: 0,
+            capacity,
+            uploaded: Vec::new(),
+        }
+    }
+}
+
+/// Implemented by `#[derive(Vertex)]`.
+pub trait VertexProvider: bytemuck::Pod {
+    /// One attribute per field, at shader locations counting up from 0.
+    const ATTRIBUTES: &'static [VertexAttribute];
+
+    /// `ATTRIBUTES` moved to shader locations counting up from `first_location`, used when
+    /// earlier vertex buffers take the lower locations.
+    fn attributes(first_location: u32) -> Vec<VertexAttribute> {
+        Self::ATTRIBUTES
+            .iter()
+            .map(|attribute| VertexAttribute {
+                shader_location: attribute.shader_location + first_location,
+                ..*attribute
+            })
+            .collect()
+    }
+
+    /// An empty instance buffer with room for `capacity` instances before it has to grow.
+    fn instance_buffer(device: &Device, capacity: u32) -> InstanceBuffer<Self> {
+        InstanceBuffer::with_capacity(device, capacity)
+    }
+
+    fn vertex_buffer(device: &Device, vertices: &[Self]) -> VertexBuffer<Self> {
+        VertexBuffer {
+            buffer: device.create_buffer_init(&BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::cast_slice(vertices),
+                usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            }),
+            len: vertices.len() as u32,
+            marker: PhantomData,
+        }
+    }
+}
+
+/// Index types of `#[index(u16 | u32)]` buffers.
+pub trait IndexElement: bytemuck::Pod {
+    const FORMAT: IndexFormat;
+}
+
+impl IndexElement for u16 {
+    const FORMAT: IndexFormat = IndexFormat::Uint16;
+}
+
+impl IndexElement for u32 {
+    const FORMAT: IndexFormat = IndexFormat::Uint32;
+}
+
+/// An index buffer of `len` indices of type `T`.
+pub struct IndexBuffer<T> {
+    pub buffer: Buffer,
+    pub len: u32,
+    marker: PhantomData<T>,
+}
+
+impl<T: IndexElement> IndexBuffer<T> {
+    pub fn new(device: &Device, indices: &[T]) -> Self {
+        Self {
+            buffer: device.create_buffer_init(&BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::cast_slice(indices),
+                usage: BufferUsages::INDEX | BufferUsages::COPY_DST,
+            }),
+            len: indices.len() as u32,
+            marker: PhantomData,
+        }
+    }
+
+    /// Writes `indices` to the start of the buffer.
+    pub fn write(&self, queue: &Queue, indices: &[T]) {
+        assert!(
+            indices.len() as u32 <= self.len,
+            "Cannot write {} indices to an index buffer of {}",
+            indices.len(),
+            self.len
+        );
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(indices));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wgpu::{BindingType, ShaderStages, TextureSampleType};
+
+    use super::*;
+
+    struct Volume {}
+
+    impl TextureProvider for Volume {
+        const LAYOUT_ENTRY: BindGroupLayoutEntry = BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Texture {
+                multisampled: false,
+                sample_type: TextureSampleType::Float { filterable: false },
+                view_dimension: TextureViewDimension::D3,
+            },
+            count: None,
+        };
+        const WIDTH: u32 = 16;
+        const HEIGHT: u32 = 16;
+        const DEPTH_OR_ARRAY_LAYERS: u32 = 16;
+        const DIMENSION: TextureDimension = TextureDimension::D3;
+        const VIEW_DIMENSION: TextureViewDimension = TextureViewDimension::D3;
+        const FORMAT: Option<TextureFormat> = Some(TextureFormat::Rgba8Unorm);
+    }
+
+    #[test]
+    fn volume_textures_are_not_render_attachments() {
+        let usage = Volume::usage(TextureUsages::all());
+        assert!(!usage.contains(TextureUsages::RENDER_ATTACHMENT));
+        assert!(usage.contains(TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST));
+    }
+
+    /// A device on the default adapter, `None` where there is none to run GPU tests on.
+    fn device() -> Option<(Device, Queue)> {
+        let instance = wgpu::Instance::default();
+        let Some(adapter) =
+            pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))
+        else {
+            eprintln!("no adapter available, skipping");
+            return None;
+        };
+        Some(
+            pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default(), None))
+                .expect("Failed to create device"),
+        )
+    }
+
+    #[test]
+    fn allocates_volume_textures() {
+        let Some((device, _queue)) = device() else {
+            return;
+        };
+
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let volume = Volume::texture(&device);
+        let error = pollster::block_on(device.pop_error_scope());
+        assert!(error.is_none(), "{}", error.unwrap());
+        assert_eq!(volume.texture.dimension(), TextureDimension::D3);
+        assert_eq!(volume.texture.depth_or_array_layers(), 16);
+    }
+
+    #[test]
+    fn instance_buffers_grow_and_skip_unchanged_writes() {
+        let Some((device, queue)) = device() else {
+            return;
+        };
+        let mut instances = InstanceBuffer::<u32>::with_capacity(&device, 2);
+        assert_eq!(instances.buffer.size(), 8);
+
+        assert!(instances.write(&device, &queue, &[1, 2]));
+        assert!(!instances.write(&device, &queue, &[1, 2]));
+        assert!(instances.write(&device, &queue, &[3, 4]));
+        assert_eq!((instances.len, instances.capacity), (2, 2));
+
+        // Growing reallocates to the next power of two and uploads.
+        assert!(instances.write(&device, &queue, &[1, 2, 3]));
+        assert_eq!((instances.len, instances.capacity), (3, 4));
+        assert_eq!(instances.buffer.size(), 16);
+        assert!(!instances.write(&device, &queue, &[1, 2, 3]));
+
+        // Shrinking keeps the buffer.
+        assert!(instances.write(&device, &queue, &[5]));
+        assert_eq!((instances.len, instances.capacity), (1, 4));
+        assert_eq!(instances.buffer.size(), 16);
+    }
+}
@@ -0,0 +1,248 @@
+//! A small WGSL preprocessor for building shader variants from one file.
+//!
+//! Lines starting with `#` are directives:
+//!
+//! - `#define NAME` or `#define NAME value` defines `NAME`, replacing an earlier definition.
+//!   Names passed to [`preprocess`] take precedence, so a file's `#define`s act as defaults.
+//! - `#ifdef NAME`, `#ifndef NAME`, `#else` and `#endif` keep or drop the lines between them.
+//! - `#import "path.wgsl"` or `#import module::name` lines are kept as they are for
+//!   `bedrock-shader` to resolve, sources preprocessed at runtime can't import.
+//!
+//! Every other line has each whole-word `NAME` replaced by its value. Directive and dropped
+//! lines are left empty, so line numbers in WGSL errors still match the original file.
+//!
+//! `bedrock-shader` runs this at compile time for `#[shader(.., defines(..))]`; call
+//! [`preprocess`] directly to build variants at runtime.
+
+use std::{collections::HashMap, fmt};
+
+/// A malformed directive, with the 1-based line it is on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreprocessError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for PreprocessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for PreprocessError {}
+
+/// Expands the directives in `source` with `defines` set beforehand, overriding any `#define` of
+/// the same name in `source`. An empty value defines a name for `#ifdef` that substitutes to
+/// nothing.
+pub fn preprocess(source: &str, defines: &[(&str, &str)]) -> Result<String, PreprocessError> {
+    let mut values = defines
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect::<HashMap<_, _>>();
+    // One entry per open `#ifdef`: whether its current branch is kept, and whether `#else`
+    // was seen.
+    let mut conditions = Vec::<(bool, bool)>::new();
+    let mut output = String::with_capacity(source.len());
+
+    for (index, line) in source.lines().enumerate() {
+        let error = |message: String| PreprocessError {
+            line: index + 1,
+            message,
+        };
+        let active = conditions.iter().all(|(kept, _)| *kept);
+        let Some(directive) = line.trim_start().strip_prefix('#') else {
+            if active {
+                substitute(line, &values, &mut output);
+            }
+            output.push('\n');
+            continue;
+        };
+        if active && directive.split_whitespace().next() == Some("import") {
+            output.push_str(line);
+        }
+        output.push('\n');
+
+        let mut words = directive.split_whitespace();
+        let keyword = words.next().unwrap_or_default();
+        let mut name = || {
+            words
+                .next()
+                .filter(|name| is_identifier(name))
+                .ok_or_else(|| error(format!("expected `#{} NAME`", keyword)))
+        };
+        match keyword {
+            "define" => {
+                let name = name()?;
+                if active && !defines.iter().any(|(defined, _)| *defined == name) {
+                    let value = directive.trim_start()["define".len()..]
+                        .trim_start()
+                        .strip_prefix(name)
+                        .unwrap_or_default()
+                        .trim();
+                    values.insert(name.to_string(), value.to_string());
+                }
+            }
+            "ifdef" => {
+                let defined = values.contains_key(name()?);
+                conditions.push((defined, false));
+            }
+            "ifndef" => {
+                let defined = values.contains_key(name()?);
+                conditions.push((!defined, false));
+            }
+            "else" => match conditions.last_mut() {
+                Some((_, true)) => return Err(error("duplicate `#else`".to_string())),
+                Some((kept, seen_else)) => {
+                    *kept = !*kept;
+                    *seen_else = true;
+                }
+                None => return Err(error("`#else` without `#ifdef`".to_string())),
+            },
+            "endif" => {
+                if conditions.pop().is_none() {
+                    return Err(error("`#endif` without `#ifdef`".to_string()));
+                }
+            }
+            "import" => {}
+            _ => {
+                return Err(error(format!(
+                    "unknown directive `#{}`, expected one of: define, ifdef, ifndef, else, endif, import",
+                    keyword
+                )))
+            }
+        }
+    }
+
+    if !conditions.is_empty() {
+        return Err(PreprocessError {
+            line: source.lines().count(),
+            message: format!("{} `#ifdef` block(s) missing `#endif`", conditions.len()),
+        });
+    }
+    Ok(output)
+}
+
+/// Appends `line` with every defined identifier replaced by its value.
+fn substitute(line: &str, values: &HashMap<String, String>, output: &mut String) {
+    let mut rest = line;
+    while let Some(start) = rest.find(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        // A letter right after a digit is a suffix or exponent, as in `1u` or `2e3`.
+        let in_number = rest[..start].ends_with(|c: char| c.is_ascii_digit());
+        let end = rest[start..]
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .map_or(rest.len(), |i| start + i);
+        let word = &rest[start..end];
+        output.push_str(&rest[..start]);
+        match values.get(word) {
+            Some(value) if !in_number => output.push_str(value),
+            _ => output.push_str(word),
+        }
+        rest = &rest[end..];
+    }
+    output.push_str(rest);
+}
+
+/// Whether `name` can be defined, an ASCII WGSL identifier.
+pub fn is_identifier(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(source: &str, defines: &[(&str, &str)]) -> Vec<String> {
+        preprocess(source, defines)
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    fn error(source: &str) -> PreprocessError {
+        preprocess(source, &[]).unwrap_err()
+    }
+
+    #[test]
+    fn keeps_the_taken_branches() {
+        let source = "\
+#define WRAP
+#ifdef WRAP
+wrap
+#else
+clamp
+#endif
+#ifndef WRAP
+no_wrap
+#endif
+#ifdef MISSING
+#ifdef WRAP
+nested
+#endif
+#endif
+end";
+        assert_eq!(
+            lines(source, &[]),
+            ["", "", "wrap", "", "", "", "", "", "", "", "", "", "", "", "end"]
+        );
+        assert_eq!(lines(source, &[("MISSING", "")])[11], "nested");
+    }
+
+    #[test]
+    fn substitutes_whole_identifiers() {
+        let source = "#define N 4\nlet a = N + N_2 + xN + f(N);";
+        assert_eq!(lines(source, &[])[1], "let a = 4 + N_2 + xN + f(4);");
+    }
+
+    #[test]
+    fn leaves_number_suffixes_alone() {
+        let source = "let a = 1e5 + 2u + e5 + u;";
+        let defines = [("e5", "E"), ("u", "U")];
+        assert_eq!(lines(source, &defines)[0], "let a = 1e5 + 2u + E + U;");
+    }
+
+    #[test]
+    fn passed_defines_override_the_file() {
+        let source = "#define RADIUS 1\nconst R = RADIUS;";
+        assert_eq!(lines(source, &[])[1], "const R = 1;");
+        assert_eq!(lines(source, &[("RADIUS", "5")])[1], "const R = 5;");
+    }
+
+    #[test]
+    fn later_file_defines_replace_earlier_ones() {
+        let source = "#define A 1\n#define A 2\nA";
+        assert_eq!(lines(source, &[])[2], "2");
+    }
+
+    #[test]
+    fn keeps_imports_in_taken_branches() {
+        let source = "#import common::quad\n#ifdef NO\n#import \"skipped.wgsl\"\n#endif";
+        assert_eq!(lines(source, &[]), ["#import common::quad", "", "", ""]);
+    }
+
+    #[test]
+    fn reports_malformed_directives() {
+        assert_eq!(
+            error("#ifdef A\n#else\n#else\n#endif"),
+            PreprocessError {
+                line: 3,
+                message: "duplicate `#else`".to_string(),
+            }
+        );
+        assert_eq!(
+            error("\n#undef A").to_string(),
+            "line 2: unknown directive `#undef`, expected one of: define, ifdef, ifndef, else, endif, import"
+        );
+        assert_eq!(error("#else").message, "`#else` without `#ifdef`");
+        assert_eq!(error("#endif").message, "`#endif` without `#ifdef`");
+        assert_eq!(error("#define 1").message, "expected `#define NAME`");
+        assert_eq!(
+            error("#ifdef A\n#ifndef B\n#endif"),
+            PreprocessError {
+                line: 3,
+                message: "1 `#ifdef` block(s) missing `#endif`".to_string(),
+            }
+        );
+    }
+}
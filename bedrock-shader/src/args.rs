@@ -0,0 +1,370 @@
+use proc_macro2::Span;
+use syn::{
+    ext::IdentExt,
+    parse::{Parse, ParseStream},
+    punctuated::Punctuated,
+    spanned::Spanned,
+    Attribute, Expr, Ident, LitStr, Token,
+};
+
+/// One `key`, `key = value` or `key(...)` item of an attribute argument list.
+pub struct Arg {
+    pub key: Ident,
+    pub value: ArgValue,
+}
+
+pub enum ArgValue {
+    Flag,
+    Expr(Box<Expr>),
+    List(Args),
+}
+
+/// A comma separated attribute argument list. Arguments are taken out by key as they are
+/// interpreted, so `finish` can report whatever is left over as unknown.
+pub struct Args {
+    pub span: Span,
+    args: Vec<Arg>,
+}
+
+impl Parse for Arg {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let key = input.call(Ident::parse_any)?;
+        let value = if input.peek(Token![=]) {
+            input.parse::<Token![=]>()?;
+            ArgValue::Expr(Box::new(input.parse()?))
+        } else if input.peek(syn::token::Paren) {
+            let content;
+            let paren = syn::parenthesized!(content in input);
+            ArgValue::List(Args::parse_list(paren.span, &content)?)
+        } else {
+            ArgValue::Flag
+        };
+        Ok(Self { key, value })
+    }
+}
+
+impl Args {
+    /// Parses `#[name(...)]`; a bare `#[name]` yields an empty list.
+    pub fn from_attr(attr: &Attribute) -> syn::Result<Self> {
+        let span = attr.path.span();
+        if attr.tokens.is_empty() {
+            return Ok(Self { span, args: vec![] });
+        }
+        attr.parse_args_with(|input: ParseStream| Self::parse_list(span, input))
+    }
+
+    pub fn parse_list(span: Span, input: ParseStream) -> syn::Result<Self> {
+        let args = Punctuated::<Arg, Token![,]>::parse_terminated(input)?;
+        let args = args.into_iter().collect::<Vec<_>>();
+        for (index, arg) in args.iter().enumerate() {
+            if let Some(duplicate) = args[..index].iter().find(|other| other.key == arg.key) {
+                let mut error =
+                    syn::Error::new(arg.key.span(), format!("duplicate option `{}`", arg.key));
+                error.combine(syn::Error::new(
+                    duplicate.key.span(),
+                    "first specified here",
+                ));
+                return Err(error);
+            }
+        }
+        Ok(Self { span, args })
+    }
+
+    fn take(&mut self, key: &str) -> Option<Arg> {
+        let index = self.args.iter().position(|arg| arg.key == key)?;
+        Some(self.args.remove(index))
+    }
+
+    /// `key = value`.
+    pub fn expr(&mut self, key: &str) -> syn::Result<Option<Expr>> {
+        match self.take(key) {
+            None => Ok(None),
+            Some(Arg {
+                value: ArgValue::Expr(expr),
+                ..
+            }) => Ok(Some(*expr)),
+            Some(arg) => Err(syn::Error::new(
+                arg.key.span(),
+                format!("expected `{} = ...`", key),
+            )),
+        }
+    }
+
+    /// `key = value`, reporting its absence at the attribute.
+    pub fn required_expr(&mut self, key: &str) -> syn::Result<Expr> {
+        match self.expr(key)? {
+            Some(expr) => Ok(expr),
+            None => Err(self.missing(key)),
+        }
+    }
+
+    fn missing(&self, key: &str) -> syn::Error {
+        match self.args.first() {
+            Some(arg) => syn::Error::new(
+                arg.key.span(),
+                format!("unknown option `{}`, missing `{} = ...`", arg.key, key),
+            ),
+            None => syn::Error::new(self.span, format!("missing `{} = ...`", key)),
+        }
+    }
+
+    /// `key = N` for an integer literal, with the literal's span.
+    pub fn int(&mut self, key: &str) -> syn::Result<Option<(Span, u32)>> {
+        let Some(expr) = self.expr(key)? else {
+            return Ok(None);
+        };
+        match &expr {
+            Expr::Lit(syn::ExprLit {
+                lit: syn::Lit::Int(value),
+                ..
+            }) => Ok(Some((expr.span(), value.base10_parse()?))),
+            _ => Err(syn::Error::new(
+                expr.span(),
+                format!("expected `{} = N` for an integer N", key),
+            )),
+        }
+    }
+
+    /// `key = "..."`.
+    pub fn lit_str(&mut self, key: &str) -> syn::Result<Option<LitStr>> {
+        let Some(expr) = self.expr(key)? else {
+            return Ok(None);
+        };
+        match expr {
+            Expr::Lit(syn::ExprLit {
+                lit: syn::Lit::Str(value),
+                ..
+            }) => Ok(Some(value)),
+            expr => Err(syn::Error::new(
+                expr.span(),
+                format!("expected `{} = \"...\"`", key),
+            )),
+        }
+    }
+
+    /// `key = name` for a single identifier.
+    pub fn ident(&mut self, key: &str) -> syn::Result<Option<Ident>> {
+        let Some(expr) = self.expr(key)? else {
+            return Ok(None);
+        };
+        if let Expr::Path(path) = &expr {
+            if let (None, Some(ident)) = (&path.qself, path.path.get_ident()) {
+                return Ok(Some(ident.clone()));
+            }
+        }
+        Err(syn::Error::new(
+            expr.span(),
+            format!("expected `{} = name`", key),
+        ))
+    }
+
+    /// `key = name` where `name` must be one of `choices`.
+    pub fn choice(&mut self, key: &str, choices: &[&str]) -> syn::Result<Option<Ident>> {
+        let Some(ident) = self.ident(key)? else {
+            return Ok(None);
+        };
+        if !choices.iter().any(|choice| ident == choice) {
+            return Err(syn::Error::new(
+                ident.span(),
+                format!("expected `{}` to be one of: {}", key, choices.join(", ")),
+            ));
+        }
+        Ok(Some(ident))
+    }
+
+    /// `key = name`, reporting its absence at the attribute.
+    pub fn required_ident(&mut self, key: &str) -> syn::Result<Ident> {
+        match self.ident(key)? {
+            Some(ident) => Ok(ident),
+            None => Err(self.missing(key)),
+        }
+    }
+
+    /// `key = name` out of `choices`, reporting its absence at the attribute.
+    pub fn required_choice(&mut self, key: &str, choices: &[&str]) -> syn::Result<Ident> {
+        match self.choice(key, choices)? {
+            Some(ident) => Ok(ident),
+            None => Err(self.missing(key)),
+        }
+    }
+
+    /// A bare `key`.
+    pub fn flag(&mut self, key: &str) -> syn::Result<bool> {
+        match self.take(key) {
+            None => Ok(false),
+            Some(Arg {
+                value: ArgValue::Flag,
+                ..
+            }) => Ok(true),
+            Some(arg) => Err(syn::Error::new(
+                arg.key.span(),
+                format!("`{}` takes no value", key),
+            )),
+        }
+    }
+
+    /// `key = true` or `key = false`.
+    pub fn bool(&mut self, key: &str) -> syn::Result<Option<bool>> {
+        let Some(expr) = self.expr(key)? else {
+            return Ok(None);
+        };
+        match &expr {
+            Expr::Lit(syn::ExprLit {
+                lit: syn::Lit::Bool(value),
+                ..
+            }) => Ok(Some(value.value)),
+            _ => Err(syn::Error::new(
+                expr.span(),
+                format!("expected `{} = true` or `{} = false`", key, key),
+            )),
+        }
+    }
+
+    /// A bare `key` or `key(...)`, the former yielding an empty list.
+    pub fn list(&mut self, key: &str) -> syn::Result<Option<Args>> {
+        match self.take(key) {
+            None => Ok(None),
+            Some(Arg {
+                key,
+                value: ArgValue::Flag,
+            }) => Ok(Some(Args {
+                span: key.span(),
+                args: vec![],
+            })),
+            Some(Arg {
+                key,
+                value: ArgValue::List(mut args),
+            }) => {
+                args.span = key.span();
+                Ok(Some(args))
+            }
+            Some(arg) => Err(syn::Error::new(
+                arg.key.span(),
+                format!("expected `{}(...)`", key),
+            )),
+        }
+    }
+
+    /// Takes every remaining argument, for lists whose keys are user-chosen names.
+    pub fn into_args(self) -> Vec<Arg> {
+        self.args
+    }
+
+    /// Rejects any argument that was not taken, listing the ones that are accepted.
+    pub fn finish(self, expected: &[&str]) -> syn::Result<()> {
+        let Some(arg) = self.args.first() else {
+            return Ok(());
+        };
+        let message = if expected.is_empty() {
+            format!(
+                "unknown option `{}`, this attribute takes no options",
+                arg.key
+            )
+        } else {
+            format!(
+                "unknown option `{}`, expected one of: {}",
+                arg.key,
+                expected.join(", ")
+            )
+        };
+        Err(syn::Error::new(arg.key.span(), message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use syn::parse::Parser;
+
+    use super::*;
+
+    fn parse(source: &str) -> syn::Result<Args> {
+        (|input: ParseStream| Args::parse_list(Span::call_site(), input)).parse_str(source)
+    }
+
+    fn error(result: syn::Result<impl Sized>) -> String {
+        match result {
+            Ok(_) => panic!("expected an error"),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn takes_values_by_key() {
+        let mut args =
+            parse(r#"width = 64, vertex = "vs", filterable, defines(A, B = 2)"#).unwrap();
+        assert!(args.flag("filterable").unwrap());
+        assert!(!args.flag("filterable").unwrap());
+        assert_eq!(args.int("width").unwrap().map(|(_, value)| value), Some(64));
+        assert_eq!(args.lit_str("vertex").unwrap().unwrap().value(), "vs");
+        let defines = args.list("defines").unwrap().unwrap().into_args();
+        let keys = defines
+            .iter()
+            .map(|arg| arg.key.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, ["A", "B"]);
+        assert!(matches!(defines[0].value, ArgValue::Flag));
+        assert!(matches!(defines[1].value, ArgValue::Expr(_)));
+        args.finish(&[]).unwrap();
+    }
+
+    #[test]
+    fn bare_key_is_an_empty_list() {
+        let mut args = parse("defines").unwrap();
+        assert!(args
+            .list("defines")
+            .unwrap()
+            .unwrap()
+            .into_args()
+            .is_empty());
+        assert!(args.list("defines").unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_duplicate_keys() {
+        assert_eq!(
+            error(parse("width = 1, width = 2")),
+            "duplicate option `width`"
+        );
+    }
+
+    #[test]
+    fn rejects_values_of_the_wrong_shape() {
+        let mut args = parse("a = 1, b, c = x, d = 1.5, e = 3").unwrap();
+        assert_eq!(error(args.flag("a")), "`a` takes no value");
+        assert_eq!(error(args.expr("b")), "expected `b = ...`");
+        assert_eq!(error(args.bool("c")), "expected `c = true` or `c = false`");
+        assert_eq!(error(args.int("d")), "expected `d = N` for an integer N");
+        assert_eq!(error(args.ident("e")), "expected `e = name`");
+    }
+
+    #[test]
+    fn choice_lists_the_accepted_values() {
+        let mut args = parse("filter = cubic").unwrap();
+        assert_eq!(
+            error(args.choice("filter", &["linear", "nearest"])),
+            "expected `filter` to be one of: linear, nearest"
+        );
+    }
+
+    #[test]
+    fn reports_missing_and_unknown_options() {
+        let mut args = parse("").unwrap();
+        assert_eq!(error(args.required_ident("name")), "missing `name = ...`");
+
+        let mut args = parse("nmae = x").unwrap();
+        assert_eq!(
+            error(args.required_ident("name")),
+            "unknown option `nmae`, missing `name = ...`"
+        );
+        assert_eq!(
+            error(args.finish(&["name", "size"])),
+            "unknown option `nmae`, expected one of: name, size"
+        );
+
+        let args = parse("size = 4").unwrap();
+        assert_eq!(
+            error(args.finish(&[])),
+            "unknown option `size`, this attribute takes no options"
+        );
+    }
+}
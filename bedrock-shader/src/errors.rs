@@ -0,0 +1,20 @@
+/// Accumulates spanned errors so every mismatch is reported in one build.
+#[derive(Default)]
+pub struct Errors(Option<syn::Error>);
+
+impl Errors {
+    pub fn push(&mut self, span: proc_macro2::Span, message: String) {
+        self.extend(syn::Error::new(span, message));
+    }
+
+    pub fn extend(&mut self, error: syn::Error) {
+        match &mut self.0 {
+            Some(errors) => errors.combine(error),
+            None => self.0 = Some(error),
+        }
+    }
+
+    pub fn finish(self) -> syn::Result<()> {
+        self.0.map_or(Ok(()), Err)
+    }
+}
@@ -0,0 +1,93 @@
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, ToTokens};
+use syn::Ident;
+use wgpu::{
+    Features, TextureFormat, TextureFormatFeatureFlags, TextureFormatFeatures, TextureSampleType,
+    TextureUsages,
+};
+
+/// A `wgpu::TextureFormat` named in an attribute, e.g. `format = Rgba16Float`.
+#[derive(Debug)]
+pub struct Format {
+    pub ident: Ident,
+    pub format: TextureFormat,
+}
+
+macro_rules! formats {
+    ($($name:ident),* $(,)?) => {
+        fn lookup(name: &str) -> Option<TextureFormat> {
+            match name {
+                $(stringify!($name) => Some(TextureFormat::$name),)*
+                _ => None,
+            }
+        }
+    };
+}
+
+formats! {
+    R8Unorm, R8Snorm, R8Uint, R8Sint,
+    R16Uint, R16Sint, R16Unorm, R16Snorm, R16Float,
+    Rg8Unorm, Rg8Snorm, Rg8Uint, Rg8Sint,
+    R32Uint, R32Sint, R32Float,
+    Rg16Uint, Rg16Sint, Rg16Unorm, Rg16Snorm, Rg16Float,
+    Rgba8Unorm, Rgba8UnormSrgb, Rgba8Snorm, Rgba8Uint, Rgba8Sint,
+    Bgra8Unorm, Bgra8UnormSrgb,
+    Rgb9e5Ufloat, Rgb10a2Uint, Rgb10a2Unorm, Rg11b10Float,
+    Rg32Uint, Rg32Sint, Rg32Float,
+    Rgba16Uint, Rgba16Sint, Rgba16Unorm, Rgba16Snorm, Rgba16Float,
+    Rgba32Uint, Rgba32Sint, Rgba32Float,
+    Stencil8, Depth16Unorm, Depth24Plus, Depth24PlusStencil8, Depth32Float, Depth32FloatStencil8,
+}
+
+impl Format {
+    /// Resolves a `wgpu::TextureFormat` variant name. Block compressed formats are not supported.
+    pub fn parse(ident: Ident) -> syn::Result<Self> {
+        match lookup(&ident.to_string()) {
+            Some(format) => Ok(Self { ident, format }),
+            None => Err(syn::Error::new(
+                ident.span(),
+                format!(
+                    "unknown texture format `{}`, expected an uncompressed `wgpu::TextureFormat` variant like `Rgba8Unorm` or `R32Float`",
+                    ident
+                ),
+            )),
+        }
+    }
+
+    /// How shaders read the format, `None` for combined depth-stencil formats.
+    pub fn sample_type(&self) -> Option<TextureSampleType> {
+        self.format.sample_type(None)
+    }
+
+    /// Whether every adapter can render to the format as a color target.
+    pub fn renderable(&self) -> bool {
+        !self.format.is_depth_stencil_format()
+            && self
+                .features()
+                .allowed_usages
+                .contains(TextureUsages::RENDER_ATTACHMENT)
+    }
+
+    /// Whether every adapter can blend color targets of the format.
+    pub fn blendable(&self) -> bool {
+        self.features()
+            .flags
+            .contains(TextureFormatFeatureFlags::BLENDABLE)
+    }
+
+    fn features(&self) -> TextureFormatFeatures {
+        self.format.guaranteed_format_features(Features::empty())
+    }
+
+    /// Bytes per texel, `None` for formats without a fixed copy size such as `Depth24Plus`.
+    pub fn block_size(&self) -> Option<u32> {
+        self.format.block_size(None)
+    }
+}
+
+impl ToTokens for Format {
+    fn to_tokens(&self, tokens: &mut TokenStream2) {
+        let ident = &self.ident;
+        tokens.extend(quote! { wgpu::TextureFormat::#ident });
+    }
+}
@@ -1,25 +1,60 @@
 use proc_macro2::TokenStream as TokenStream2;
 use quote::{format_ident, quote};
+use wgpu::TextureSampleType;
 
-use crate::schema;
+use crate::{format::Format, schema, source::ShaderModule};
 
 impl schema::Entry {
-    pub fn group_layout_entry_impl(
-        &self,
-        binding: u32,
-        visibility: proc_macro2::TokenStream,
-    ) -> TokenStream2 {
-        let binding_type = match self.variant {
-            schema::EntryVariant::Texture2D { .. } => quote! {wgpu::BindingType::Texture {
-                multisampled: false,
-                sample_type: wgpu::TextureSampleType::Float { filterable: false },
-                view_dimension: wgpu::TextureViewDimension::D2,
-            }},
-            schema::EntryVariant::Uniform { .. } => quote! {wgpu::BindingType::Buffer {
+    pub fn group_layout_entry_impl(&self) -> TokenStream2 {
+        let binding = self.binding;
+        let visibility = self.visibility.shader_stages();
+        let binding_type = match &self.variant {
+            schema::EntryVariant::Texture {
+                kind,
+                filterable,
+                format,
+                ..
+            } => {
+                let view_dimension = kind.view_dimension();
+                let sample_type = match format.as_ref().and_then(Format::sample_type) {
+                    Some(TextureSampleType::Depth) => quote! { Depth },
+                    Some(TextureSampleType::Uint) => quote! { Uint },
+                    Some(TextureSampleType::Sint) => quote! { Sint },
+                    _ => quote! { Float { filterable: #filterable } },
+                };
+                quote! {wgpu::BindingType::Texture {
+                    multisampled: false,
+                    sample_type: wgpu::TextureSampleType::#sample_type,
+                    view_dimension: wgpu::TextureViewDimension::#view_dimension,
+                }}
+            }
+            schema::EntryVariant::StorageTexture { format, access, .. } => {
+                quote! {wgpu::BindingType::StorageTexture {
+                    access: wgpu::StorageTextureAccess::#access,
+                    format: #format,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                }}
+            }
+            schema::EntryVariant::Uniform { dynamic, .. } => quote! {wgpu::BindingType::Buffer {
                 ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: #dynamic,
+                min_binding_size: None,
+            }},
+            schema::EntryVariant::Storage { read_only } => quote! {wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: #read_only },
                 has_dynamic_offset: false,
                 min_binding_size: None,
             }},
+            schema::EntryVariant::Sampler {
+                filter, compare, ..
+            } => {
+                let sampler_type = match compare {
+                    Some(_) => quote! { Comparison },
+                    None if filter == "Linear" => quote! { Filtering },
+                    None => quote! { NonFiltering },
+                };
+                quote! {wgpu::BindingType::Sampler(wgpu::SamplerBindingType::#sampler_type)}
+            }
         };
         let layout_entry_const = quote! {
             const LAYOUT_ENTRY: wgpu::BindGroupLayoutEntry = wgpu::BindGroupLayoutEntry {
@@ -29,21 +64,839 @@ impl schema::Entry {
                 count: None,
             };
         };
-        let struct_ident = format_ident!("{}", &self.ty);
-        match self.variant {
-            schema::EntryVariant::Texture2D { width, height } => quote! {
-                impl TextureProvider for #struct_ident {
+        let struct_ident = &self.ty;
+        match &self.variant {
+            schema::EntryVariant::Texture {
+                kind,
+                width,
+                height,
+                depth_or_array_layers,
+                filterable,
+                format,
+            } => {
+                let dimension = kind.dimension();
+                let view_dimension = kind.view_dimension();
+                let format_consts = format.as_ref().map(|format| {
+                    let block_size = format.block_size().unwrap();
+                    quote! {
+                        const FORMAT: Option<wgpu::TextureFormat> = Some(#format);
+                        const BYTES_PER_TEXEL: u32 = #block_size;
+                    }
+                });
+                quote! {
+                    impl TextureProvider for #struct_ident {
+                        #layout_entry_const
+                        const WIDTH: u32 = #width;
+                        const HEIGHT: u32 = #height;
+                        const DEPTH_OR_ARRAY_LAYERS: u32 = #depth_or_array_layers;
+                        const DIMENSION: wgpu::TextureDimension = wgpu::TextureDimension::#dimension;
+                        const VIEW_DIMENSION: wgpu::TextureViewDimension =
+                            wgpu::TextureViewDimension::#view_dimension;
+                        const FILTERABLE: bool = #filterable;
+                        #format_consts
+                    }
+                }
+            }
+            schema::EntryVariant::StorageTexture {
+                width,
+                height,
+                format,
+                access,
+            } => {
+                let block_size = format.block_size().unwrap();
+                quote! {
+                    impl TextureProvider for #struct_ident {
+                        #layout_entry_const
+                        const WIDTH: u32 = #width;
+                        const HEIGHT: u32 = #height;
+                        const FORMAT: Option<wgpu::TextureFormat> = Some(#format);
+                        const BYTES_PER_TEXEL: u32 = #block_size;
+                        const STORAGE_ACCESS: Option<wgpu::StorageTextureAccess> =
+                            Some(wgpu::StorageTextureAccess::#access);
+                    }
+                }
+            }
+            schema::EntryVariant::Uniform { size, .. } => {
+                let size = match size {
+                    Some(size) => quote! { #size },
+                    None => quote! {
+                        <Self as bedrock_lib::encase::ShaderSize>::SHADER_SIZE.get()
+                    },
+                };
+                quote! {
+                    impl UniformProvider for #struct_ident {
+                        #layout_entry_const
+                        const SIZE: u64 = #size;
+                    }
+                }
+            }
+            schema::EntryVariant::Storage { .. } => quote! {
+                impl StorageProvider for #struct_ident {
                     #layout_entry_const
-                    const WIDTH: u32 = #width;
-                    const HEIGHT: u32 = #height;
                 }
             },
-            schema::EntryVariant::Uniform { size } => quote! {
-                impl UniformProvider for #struct_ident {
-                    #layout_entry_const
-                    const SIZE: u64 = #size;
+            schema::EntryVariant::Sampler {
+                filter,
+                address,
+                compare,
+            } => {
+                let compare = match compare {
+                    Some(compare) => quote! { Some(wgpu::CompareFunction::#compare) },
+                    None => quote! { None },
+                };
+                quote! {
+                    impl SamplerProvider for #struct_ident {
+                        #layout_entry_const
+                        const DESCRIPTOR: wgpu::SamplerDescriptor<'static> = wgpu::SamplerDescriptor {
+                            label: None,
+                            address_mode_u: wgpu::AddressMode::#address,
+                            address_mode_v: wgpu::AddressMode::#address,
+                            address_mode_w: wgpu::AddressMode::#address,
+                            mag_filter: wgpu::FilterMode::#filter,
+                            min_filter: wgpu::FilterMode::#filter,
+                            mipmap_filter: wgpu::FilterMode::#filter,
+                            lod_min_clamp: 0.0,
+                            lod_max_clamp: 32.0,
+                            compare: #compare,
+                            anisotropy_clamp: 1,
+                            border_color: None,
+                        };
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Bind group code shared by render and compute pipelines.
+pub struct BindGroupsGen {
+    /// Provider impls for the entry types, emitted next to the shader struct.
+    pub provider_impls: TokenStream2,
+    /// `group_N_layout_descriptor` and `group_N_layout` functions on the shader struct.
+    pub layout_fns: TokenStream2,
+    /// `GROUP_N_LAYOUT` statics in the shader module.
+    pub layout_cells: TokenStream2,
+    /// Initializes every `GROUP_N_LAYOUT` into a `group_N_layout` local of `pipeline`.
+    pub layout_cell_defs: TokenStream2,
+    pub layout_cell_idents: TokenStream2,
+    /// `group_N` bind group builders.
+    pub builders: TokenStream2,
+    /// `group_N: &'a wgpu::BindGroup` parameters of the encoder functions.
+    pub encoder_args: TokenStream2,
+    /// Binds every group on a render or compute pass named `pass`.
+    pub pass_binds: TokenStream2,
+}
+
+impl schema::Shader {
+    pub fn bind_groups_gen(&self, shader_ident: &syn::Ident) -> BindGroupsGen {
+        let mut extra_gen = Vec::<TokenStream2>::new();
+        let mut impl_gen = Vec::<TokenStream2>::new();
+        let mut group_layout_cells = Vec::<TokenStream2>::new();
+        let mut group_layout_cell_defs = Vec::<TokenStream2>::new();
+        let mut group_layout_cell_idents = Vec::<TokenStream2>::new();
+        let mut group_builders = Vec::<TokenStream2>::new();
+        let mut draw_encoder_arg_groups = Vec::<TokenStream2>::new();
+
+        // Pipeline layouts can't skip group indices, gaps get a shared empty group.
+        let group_count = self.bind_groups.last().map_or(0, |group| group.index + 1);
+        let gaps = (0..group_count)
+            .filter(|index| self.bind_groups.iter().all(|group| group.index != *index))
+            .collect::<Vec<_>>();
+        if !gaps.is_empty() {
+            group_layout_cells.push(quote! {
+                pub static EMPTY_GROUP_LAYOUT: OnceLock<wgpu::BindGroupLayout> = OnceLock::new();
+                pub static EMPTY_GROUP: OnceLock<wgpu::BindGroup> = OnceLock::new();
+            });
+            group_layout_cell_defs.push(quote! {
+                let empty_group_layout = EMPTY_GROUP_LAYOUT.get_or_init(|| {
+                    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                        label: None,
+                        entries: &[],
+                    })
+                });
+                EMPTY_GROUP.get_or_init(|| {
+                    device.create_bind_group(&wgpu::BindGroupDescriptor {
+                        label: None,
+                        layout: empty_group_layout,
+                        entries: &[],
+                    })
+                });
+            });
+        }
+
+        for group_index in 0..group_count {
+            let Some(group) = self
+                .bind_groups
+                .iter()
+                .find(|group| group.index == group_index)
+            else {
+                group_layout_cell_idents.push(quote! {
+                    empty_group_layout,
+                });
+                continue;
+            };
+            let (group_layout_entry_impls, group_layout_entry_constant_idents): (
+                TokenStream2,
+                TokenStream2,
+            ) = group
+                .entries
+                .iter()
+                .map(|entry| {
+                    let entry_type = &entry.ty;
+                    (
+                        entry.group_layout_entry_impl(),
+                        quote! { #entry_type::LAYOUT_ENTRY, },
+                    )
+                })
+                .unzip();
+
+            let descriptor_ident = format_ident!("group_{}_layout_descriptor", group_index);
+            let group_layout_descriptor_static = quote! {
+                pub fn #descriptor_ident() -> wgpu::BindGroupLayoutDescriptor<'static> {
+                    wgpu::BindGroupLayoutDescriptor{
+                        label: None,
+                        entries: &[
+                            #group_layout_entry_constant_idents
+                        ]
+                    }
+                }
+            };
+
+            let layout_ident = format_ident!("group_{}_layout", group_index);
+            let group_layout_builder = quote! {
+                pub fn #layout_ident(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+                    device.create_bind_group_layout(&Self::#descriptor_ident())
+                }
+            };
+
+            extra_gen.push(quote! {
+                #group_layout_entry_impls
+            });
+            impl_gen.push(quote! {
+                #group_layout_descriptor_static
+                #group_layout_builder
+            });
+
+            let static_ident = format_ident!("GROUP_{}_LAYOUT", group_index);
+            group_layout_cells.push(quote! {
+                pub static #static_ident: OnceLock<wgpu::BindGroupLayout> = OnceLock::new();
+            });
+
+            group_layout_cell_defs.push(quote! {
+                let #layout_ident = #static_ident.get_or_init(|| #shader_ident::#layout_ident(device));
+            });
+
+            group_layout_cell_idents.push(quote! {
+                #layout_ident,
+            });
+
+            let mut group_builder_args = Vec::<TokenStream2>::new();
+            let mut group_builder_binding_resources = Vec::<TokenStream2>::new();
+            for (entry_index, entry) in group.entries.iter().enumerate() {
+                let arg_ident = format_ident!("entry_{entry_index}");
+                match entry.variant {
+                    schema::EntryVariant::Texture { .. }
+                    | schema::EntryVariant::StorageTexture { .. } => {
+                        group_builder_args.push(quote! {
+                            #arg_ident: &ShaderTexture,
+                        });
+                        group_builder_binding_resources.push(quote! {
+                            wgpu::BindingResource::TextureView(&#arg_ident.view)
+                        });
+                    }
+                    schema::EntryVariant::Uniform { dynamic: false, .. } => {
+                        let entry_type = &entry.ty;
+                        group_builder_args.push(quote! {
+                            #arg_ident: &ShaderUniform<#entry_type>,
+                        });
+                        group_builder_binding_resources.push(quote! {
+                            wgpu::BindingResource::Buffer(#arg_ident.buffer.as_entire_buffer_binding())
+                        });
+                    }
+                    schema::EntryVariant::Uniform { dynamic: true, .. } => {
+                        let entry_type = &entry.ty;
+                        group_builder_args.push(quote! {
+                            #arg_ident: &UniformRing<#entry_type>,
+                        });
+                        group_builder_binding_resources.push(quote! {
+                            wgpu::BindingResource::Buffer(#arg_ident.binding())
+                        });
+                    }
+                    schema::EntryVariant::Sampler { .. } => {
+                        group_builder_args.push(quote! {
+                            #arg_ident: &ShaderSampler,
+                        });
+                        group_builder_binding_resources.push(quote! {
+                            wgpu::BindingResource::Sampler(&#arg_ident.sampler)
+                        });
+                    }
+                    schema::EntryVariant::Storage { .. } => {
+                        let entry_type = &entry.ty;
+                        group_builder_args.push(quote! {
+                            #arg_ident: &ShaderStorage<#entry_type>,
+                        });
+                        group_builder_binding_resources.push(quote! {
+                            wgpu::BindingResource::Buffer(#arg_ident.buffer.as_entire_buffer_binding())
+                        });
+                    }
+                }
+            }
+            let group_builder_args = group_builder_args.into_iter().collect::<TokenStream2>();
+            let group_builder_binding_entries = group_builder_binding_resources
+                .into_iter()
+                .zip(&group.entries)
+                .map(|(resource, entry)| {
+                    let binding = entry.binding;
+                    quote! {
+                        wgpu::BindGroupEntry {
+                            binding: #binding,
+                            resource: #resource,
+                        },
+                    }
+                })
+                .collect::<TokenStream2>();
+
+            let group_builder_ident = format_ident!("group_{group_index}");
+            group_builders.push(quote! {
+                pub fn #group_builder_ident(device: &wgpu::Device, #group_builder_args) -> wgpu::BindGroup {
+                    device.create_bind_group(&wgpu::BindGroupDescriptor {
+                        label: None,
+                        layout: #static_ident.get().unwrap(),
+                        entries: &[#group_builder_binding_entries],
+                    })
                 }
+            });
+
+            draw_encoder_arg_groups.push(quote! {
+                #group_builder_ident: &'a wgpu::BindGroup,
+            });
+            let dynamic_offsets = group.dynamic_offsets();
+            if dynamic_offsets > 0 {
+                let offsets_ident = format_ident!("group_{}_offsets", group_index);
+                draw_encoder_arg_groups.push(quote! {
+                    #offsets_ident: [u32; #dynamic_offsets],
+                });
+            }
+        }
+
+        let mut pass_binds = Vec::<TokenStream2>::new();
+        for group in &self.bind_groups {
+            let group_index = group.index;
+            let group_ident = format_ident!("group_{}", group_index);
+            let offsets = if group.dynamic_offsets() > 0 {
+                let offsets_ident = format_ident!("group_{}_offsets", group_index);
+                quote! { &#offsets_ident }
+            } else {
+                quote! { &[] }
+            };
+            pass_binds.push(quote! {
+                pass.set_bind_group(#group_index, #group_ident, #offsets);
+            });
+        }
+        for group_index in gaps {
+            pass_binds.push(quote! {
+                pass.set_bind_group(#group_index, EMPTY_GROUP.get().unwrap(), &[]);
+            });
+        }
+
+        BindGroupsGen {
+            provider_impls: extra_gen.into_iter().collect(),
+            layout_fns: impl_gen.into_iter().collect(),
+            layout_cells: group_layout_cells.into_iter().collect(),
+            layout_cell_defs: group_layout_cell_defs.into_iter().collect(),
+            layout_cell_idents: group_layout_cell_idents.into_iter().collect(),
+            builders: group_builders.into_iter().collect(),
+            encoder_args: draw_encoder_arg_groups.into_iter().collect(),
+            pass_binds: pass_binds.into_iter().collect(),
+        }
+    }
+}
+
+/// Vertex buffer code of render pipelines.
+pub struct VertexBuffersGen {
+    /// `vertex_buffer_N_attributes` locals of `pipeline`, shifted past earlier buffers' locations.
+    pub attribute_defs: TokenStream2,
+    /// `wgpu::VertexBufferLayout`s for the pipeline's `VertexState`.
+    pub layouts: TokenStream2,
+    /// `vertex_N: &'a VertexBuffer<T>` and `instance_N: &'a InstanceBuffer<T>` parameters of
+    /// `draw_encoder`.
+    pub encoder_args: TokenStream2,
+    /// Sets every vertex buffer on a render pass named `pass`.
+    pub pass_binds: TokenStream2,
+}
+
+impl schema::Shader {
+    /// Per-vertex buffers first, then per-instance ones, so instance attribute locations
+    /// continue after the vertex attributes.
+    pub fn vertex_buffers_gen(&self) -> VertexBuffersGen {
+        let buffers = self
+            .vertex_buffers
+            .iter()
+            .filter(|vertex| !vertex.instance)
+            .chain(self.vertex_buffers.iter().filter(|vertex| vertex.instance))
+            .collect::<Vec<_>>();
+        let mut attribute_defs = vec![];
+        let mut layouts = vec![];
+        let mut encoder_args = vec![];
+        let mut pass_binds = vec![];
+        for (slot, vertex) in buffers.iter().enumerate() {
+            let ty = &vertex.ty;
+            let previous = buffers[..slot].iter().map(|vertex| &vertex.ty);
+            let attributes_ident = format_ident!("vertex_buffer_{}_attributes", slot);
+            attribute_defs.push(quote! {
+                let #attributes_ident = <#ty as bedrock_lib::VertexProvider>::attributes(
+                    0 #(+ <#previous as bedrock_lib::VertexProvider>::ATTRIBUTES.len() as u32)*
+                );
+            });
+            let step_mode = if vertex.instance {
+                quote! { wgpu::VertexStepMode::Instance }
+            } else {
+                quote! { wgpu::VertexStepMode::Vertex }
+            };
+            layouts.push(quote! {
+                wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<#ty>() as u64,
+                    step_mode: #step_mode,
+                    attributes: &#attributes_ident,
+                },
+            });
+            let kind_index = buffers[..slot]
+                .iter()
+                .filter(|other| other.instance == vertex.instance)
+                .count();
+            let (arg_ident, arg_ty) = if vertex.instance {
+                (
+                    format_ident!("instance_{}", kind_index),
+                    quote! { InstanceBuffer<#ty> },
+                )
+            } else {
+                (
+                    format_ident!("vertex_{}", kind_index),
+                    quote! { VertexBuffer<#ty> },
+                )
+            };
+            encoder_args.push(quote! {
+                #arg_ident: &'a #arg_ty,
+            });
+            let slot = slot as u32;
+            pass_binds.push(quote! {
+                pass.set_vertex_buffer(#slot, #arg_ident.buffer.slice(..));
+            });
+        }
+        VertexBuffersGen {
+            attribute_defs: attribute_defs.into_iter().collect(),
+            layouts: layouts.into_iter().collect(),
+            encoder_args: encoder_args.into_iter().collect(),
+            pass_binds: pass_binds.into_iter().collect(),
+        }
+    }
+}
+
+impl schema::Shader {
+    pub fn primitive_state(&self) -> TokenStream2 {
+        let primitive = &self.primitive;
+        let topology = debug_ident(primitive.topology);
+        let strip_index_format = match primitive.strip_index_format {
+            Some(format) => {
+                let format = debug_ident(format);
+                quote! { Some(wgpu::IndexFormat::#format) }
+            }
+            None => quote! { None },
+        };
+        let front_face = debug_ident(primitive.front_face);
+        let cull_mode = match primitive.cull_mode {
+            Some(face) => {
+                let face = debug_ident(face);
+                quote! { Some(wgpu::Face::#face) }
+            }
+            None => quote! { None },
+        };
+        let unclipped_depth = primitive.unclipped_depth;
+        let polygon_mode = debug_ident(primitive.polygon_mode);
+        quote! {
+            wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::#topology,
+                strip_index_format: #strip_index_format,
+                front_face: wgpu::FrontFace::#front_face,
+                cull_mode: #cull_mode,
+                unclipped_depth: #unclipped_depth,
+                polygon_mode: wgpu::PolygonMode::#polygon_mode,
+                conservative: false,
+            }
+        }
+    }
+
+    /// `REQUIRED_FEATURES` and the checks `pipeline` runs against the device first, so missing
+    /// features fail with the shader's name rather than a wgpu validation error.
+    pub fn device_checks_gen(&self, shader_ident: &syn::Ident) -> DeviceChecksGen {
+        let required_features = self.required_features();
+        let missing_features_message = format!(
+            "`{}` needs device features {{:?}} that the adapter does not support",
+            shader_ident
+        );
+        let push_constants_check = self.push_constants.as_ref().map(|push_constants| {
+            let ty = &push_constants.ty;
+            let message = format!(
+                "`{}` push constants exceed the device's `max_push_constant_size` of {{}} bytes",
+                shader_ident
+            );
+            quote! {
+                let max_push_constant_size = device.limits().max_push_constant_size;
+                assert!(
+                    std::mem::size_of::<#ty>() as u32 <= max_push_constant_size,
+                    #message,
+                    max_push_constant_size
+                );
+            }
+        });
+        DeviceChecksGen {
+            required_features: quote! {
+                /// Device features the pipeline needs, `Driver` requests them when available.
+                pub const REQUIRED_FEATURES: wgpu::Features =
+                    wgpu::Features::empty() #(.union(wgpu::Features::#required_features))*;
             },
+            checks: quote! {
+                let missing_features = REQUIRED_FEATURES.difference(device.features());
+                assert!(missing_features.is_empty(), #missing_features_message, missing_features);
+                #push_constants_check
+            },
+        }
+    }
+
+    /// Device features the pipeline options need, as `wgpu::Features` constant names.
+    fn required_features(&self) -> Vec<syn::Ident> {
+        let mut features = vec![];
+        if self.push_constants.is_some() {
+            features.push(format_ident!("PUSH_CONSTANTS"));
+        }
+        match self.primitive.polygon_mode {
+            wgpu::PolygonMode::Line => features.push(format_ident!("POLYGON_MODE_LINE")),
+            wgpu::PolygonMode::Point => features.push(format_ident!("POLYGON_MODE_POINT")),
+            wgpu::PolygonMode::Fill => {}
+        }
+        if self.primitive.unclipped_depth {
+            features.push(format_ident!("DEPTH_CLIP_CONTROL"));
+        }
+        features
+    }
+}
+
+/// Device feature and limit checks of `pipeline`.
+pub struct DeviceChecksGen {
+    pub required_features: TokenStream2,
+    pub checks: TokenStream2,
+}
+
+impl schema::Shader {
+    /// Fails compilation when a typed `#[uniform]` differs in size from its WGSL global, which
+    /// wgpu would otherwise only report when drawing.
+    pub fn uniform_size_asserts(&self, module: &ShaderModule) -> TokenStream2 {
+        let mut asserts = Vec::<TokenStream2>::new();
+        for group in &self.bind_groups {
+            for entry in &group.entries {
+                let schema::EntryVariant::Uniform { size: None, .. } = entry.variant else {
+                    continue;
+                };
+                let Some(wgsl_size) = module.uniform_size(group.index, entry.binding) else {
+                    continue;
+                };
+                let ty = &entry.ty;
+                let wgsl_size = wgsl_size as u64;
+                let message = format!(
+                    "#[uniform] `{}` must be {} bytes, the size of the WGSL uniform at @group({}) @binding({})",
+                    entry.ident, wgsl_size, group.index, entry.binding
+                );
+                asserts.push(quote! {
+                    const _: () = assert!(
+                        <#ty as bedrock_lib::encase::ShaderSize>::SHADER_SIZE.get() == #wgsl_size,
+                        #message
+                    );
+                });
+            }
+        }
+        asserts.into_iter().collect()
+    }
+}
+
+/// Push constant code of a pipeline with a `#[push_constants]` field.
+#[derive(Default)]
+pub struct PushConstantsGen {
+    /// `wgpu::PushConstantRange`s of the pipeline layout, a constant so they live long enough.
+    pub ranges: TokenStream2,
+    /// Fails compilation when the Rust type and the WGSL global differ in size.
+    pub size_assert: TokenStream2,
+    pub encoder_arg: TokenStream2,
+    /// Sets the push constants on a render or compute pass named `pass`.
+    pub pass_set: TokenStream2,
+}
+
+impl schema::PushConstants {
+    pub fn push_constants_gen(&self, wgsl_size: Option<u32>, compute: bool) -> PushConstantsGen {
+        let ty = &self.ty;
+        let stages = &self.stages;
+        let stages = quote! { wgpu::ShaderStages::empty() #(.union(wgpu::ShaderStages::#stages))* };
+        let size_assert = wgsl_size.map(|wgsl_size| {
+            let message = format!(
+                "#[push_constants] type must be {} bytes, the size of the WGSL `var<push_constant>`",
+                wgsl_size
+            );
+            quote! {
+                const _: () = assert!(std::mem::size_of::<#ty>() == #wgsl_size as usize, #message);
+            }
+        });
+        let pass_set = if compute {
+            quote! {
+                pass.set_push_constants(0, bedrock_lib::bytemuck::bytes_of(push_constants));
+            }
+        } else {
+            quote! {
+                pass.set_push_constants(#stages, 0, bedrock_lib::bytemuck::bytes_of(push_constants));
+            }
+        };
+        PushConstantsGen {
+            ranges: quote! {
+                wgpu::PushConstantRange {
+                    stages: #stages,
+                    range: 0..std::mem::size_of::<#ty>() as u32,
+                },
+            },
+            size_assert: size_assert.unwrap_or_default(),
+            encoder_arg: quote! {
+                push_constants: &#ty,
+            },
+            pass_set,
+        }
+    }
+}
+
+impl schema::Output {
+    /// The declared format, or the swapchain format looked up when the pipeline is built.
+    pub fn format(&self) -> TokenStream2 {
+        match &self.format {
+            Some(format) => quote! { #format },
+            None => quote! { *driver::SWAP_CHAIN_FORMAT.get().unwrap() },
+        }
+    }
+
+    /// The declared `load` and `store` ops, the `OutputOps` default.
+    pub fn operations(&self) -> TokenStream2 {
+        let load = match &self.clear {
+            Some(color) => {
+                let [r, g, b, a] = [&color[0], &color[1], &color[2], &color[3]];
+                quote! {
+                    wgpu::LoadOp::Clear(wgpu::Color {
+                        r: (#r) as f64,
+                        g: (#g) as f64,
+                        b: (#b) as f64,
+                        a: (#a) as f64,
+                    })
+                }
+            }
+            None => quote! { wgpu::LoadOp::Load },
+        };
+        let store = if self.store {
+            quote! { wgpu::StoreOp::Store }
+        } else {
+            quote! { wgpu::StoreOp::Discard }
+        };
+        quote! {
+            wgpu::Operations {
+                load: #load,
+                store: #store,
+            }
+        }
+    }
+
+    pub fn color_target_state(&self) -> TokenStream2 {
+        let format = self.format();
+        let blend = match self
+            .blend
+            .as_ref()
+            .map(|blend| blend.to_string())
+            .as_deref()
+        {
+            None => quote! { None },
+            Some("Alpha") => quote! { Some(wgpu::BlendState::ALPHA_BLENDING) },
+            Some("Additive") => quote! {
+                Some(wgpu::BlendState {
+                    color: wgpu::BlendComponent {
+                        src_factor: wgpu::BlendFactor::One,
+                        dst_factor: wgpu::BlendFactor::One,
+                        operation: wgpu::BlendOperation::Add,
+                    },
+                    alpha: wgpu::BlendComponent {
+                        src_factor: wgpu::BlendFactor::One,
+                        dst_factor: wgpu::BlendFactor::One,
+                        operation: wgpu::BlendOperation::Add,
+                    },
+                })
+            },
+            Some(_) => quote! { Some(wgpu::BlendState::REPLACE) },
+        };
+        let write_mask = &self.write_mask;
+        quote! {
+            Some(wgpu::ColorTargetState {
+                format: #format,
+                blend: #blend,
+                write_mask: #(wgpu::ColorWrites::#write_mask)|*,
+            }),
+        }
+    }
+}
+
+impl schema::Depth {
+    pub fn depth_stencil_state(&self) -> TokenStream2 {
+        let format = &self.format;
+        let write = self.write;
+        let compare = &self.compare;
+        let stencil = match &self.stencil {
+            Some(stencil) => {
+                let schema::Stencil {
+                    compare,
+                    fail,
+                    depth_fail,
+                    pass,
+                    read_mask,
+                    write_mask,
+                    ..
+                } = stencil;
+                quote! {
+                    {
+                        let face = wgpu::StencilFaceState {
+                            compare: wgpu::CompareFunction::#compare,
+                            fail_op: wgpu::StencilOperation::#fail,
+                            depth_fail_op: wgpu::StencilOperation::#depth_fail,
+                            pass_op: wgpu::StencilOperation::#pass,
+                        };
+                        wgpu::StencilState {
+                            front: face,
+                            back: face,
+                            read_mask: #read_mask,
+                            write_mask: #write_mask,
+                        }
+                    }
+                }
+            }
+            None => quote! { wgpu::StencilState::default() },
+        };
+        quote! {
+            Some(wgpu::DepthStencilState {
+                format: #format,
+                depth_write_enabled: #write,
+                depth_compare: wgpu::CompareFunction::#compare,
+                stencil: #stencil,
+                bias: wgpu::DepthBiasState::default(),
+            })
+        }
+    }
+
+    /// The attachment of the `depth` parameter of `draw_encoder`.
+    pub fn attachment(&self) -> TokenStream2 {
+        let depth_load = match &self.clear {
+            Some(clear) => quote! { wgpu::LoadOp::Clear((#clear) as f32) },
+            None => quote! { wgpu::LoadOp::Load },
+        };
+        let stencil_ops = match &self.stencil {
+            Some(stencil) => {
+                let stencil_load = match &stencil.clear {
+                    Some(clear) => quote! { wgpu::LoadOp::Clear((#clear) as u32) },
+                    None => quote! { wgpu::LoadOp::Load },
+                };
+                quote! {
+                    Some(wgpu::Operations {
+                        load: #stencil_load,
+                        store: wgpu::StoreOp::Store,
+                    })
+                }
+            }
+            None => quote! { None },
+        };
+        quote! {
+            Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &depth.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: #depth_load,
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: #stencil_ops,
+            })
+        }
+    }
+}
+
+/// `draw_indexed_encoder` parameters and render pass setup for the shader's `#[index]` buffer.
+pub struct IndexBufferGen {
+    pub encoder_args: TokenStream2,
+    pub pass_bind: TokenStream2,
+}
+
+impl schema::Index {
+    pub fn index_buffer_gen(&self) -> IndexBufferGen {
+        let element = &self.element;
+        IndexBufferGen {
+            encoder_args: quote! {
+                index: &'a IndexBuffer<#element>,
+                indices: Range<u32>,
+                base_vertex: i32,
+            },
+            pass_bind: quote! {
+                pass.set_index_buffer(
+                    index.buffer.slice(..),
+                    <#element as bedrock_lib::IndexElement>::FORMAT,
+                );
+            },
+        }
+    }
+}
+
+impl schema::Group {
+    /// Number of `#[uniform(dynamic)]` entries, whose offsets `set_bind_group` takes in
+    /// binding order.
+    fn dynamic_offsets(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|entry| {
+                matches!(
+                    entry.variant,
+                    schema::EntryVariant::Uniform { dynamic: true, .. }
+                )
+            })
+            .count()
+    }
+}
+
+impl schema::Visibility {
+    /// The `wgpu::ShaderStages` of a layout entry.
+    fn shader_stages(&self) -> TokenStream2 {
+        let stages = [
+            (self.vertex, quote! { VERTEX }),
+            (self.fragment, quote! { FRAGMENT }),
+            (self.compute, quote! { COMPUTE }),
+        ]
+        .into_iter()
+        .filter(|(visible, _)| *visible)
+        .map(|(_, stage)| stage);
+        // `|` isn't const for bitflags, layout entries are constants.
+        quote! { wgpu::ShaderStages::empty() #(.union(wgpu::ShaderStages::#stages))* }
+    }
+}
+
+impl schema::TextureKind {
+    /// The `wgpu::TextureDimension` variant the texture is allocated with.
+    fn dimension(self) -> TokenStream2 {
+        match self {
+            schema::TextureKind::D3 => quote! { D3 },
+            _ => quote! { D2 },
+        }
+    }
+
+    /// The `wgpu::TextureViewDimension` variant shaders bind the texture as.
+    fn view_dimension(self) -> TokenStream2 {
+        match self {
+            schema::TextureKind::D2 => quote! { D2 },
+            schema::TextureKind::D2Array => quote! { D2Array },
+            schema::TextureKind::D3 => quote! { D3 },
+            schema::TextureKind::Cube => quote! { Cube },
         }
     }
 }
@@ -84,3 +937,8 @@ impl schema::Entry {
 //         }
 //     }
 // }
+
+/// The `wgpu` variant name of an enum value, e.g. `TriangleList`.
+fn debug_ident(value: impl std::fmt::Debug) -> syn::Ident {
+    format_ident!("{}", format!("{:?}", value))
+}
@@ -1,299 +1,682 @@
+use naga::ShaderStage;
 use proc_macro::TokenStream;
 use proc_macro2::TokenStream as TokenStream2;
 
 use quote::{format_ident, quote};
-use syn::{parse_macro_input, DeriveInput};
+use syn::{parse_macro_input, spanned::Spanned, DeriveInput};
 
+mod args;
+mod errors;
+mod format;
 mod gen;
 mod parser;
+mod reflect;
 mod schema;
+mod source;
+mod validate;
+mod vertex;
 
+use gen::{BindGroupsGen, DeviceChecksGen, IndexBufferGen, PushConstantsGen, VertexBuffersGen};
 use parser::*;
 use schema::*;
-
+use source::ShaderSource;
+
+/// Generates a render pipeline, bind group layouts and builders for a WGSL file.
+///
+/// `#[shader("shader.wgsl")]` reads the interface from the struct's attributed fields, while
+/// `#[shader("shader.wgsl", reflect(width = .., height = ..))]` on an empty struct derives it
+/// from the WGSL module instead. Paths resolve relative to the calling file, then the crate root.
+///
+/// The pipeline uses the `vs_main` and `fs_main` entry points unless `vertex = "..."` or
+/// `fragment = "..."` names others, so one WGSL file can back several pipelines. `vertex_only`
+/// drops the fragment stage for depth-only passes.
+///
+/// `defines(RADIUS = 2, WRAP)` sets names for the file's `#define`/`#ifdef` directives before it
+/// is validated, overriding its own `#define`s, so each struct with different defines gets its
+/// own pipeline variant.
+///
+/// `#import "common/quad.wgsl"` pulls in a file relative to the importing one, and
+/// `#import common::quad` the module `common/quad.wgsl` next to the `#[shader]` file. Each module
+/// is included once however often it is imported, with the same `defines`.
+///
+/// Groups and bindings number from 0 in declaration order. `#[group(vertex, index = 2)]` and
+/// `#[uniform(binding = 3)]` set them explicitly, gaps are allowed, and an entry's
+/// `visibility = vertex | fragment | compute` overrides its group's.
 #[proc_macro_attribute]
 pub fn shader(attr: TokenStream, item: TokenStream) -> TokenStream {
-    let attr = syn::parse_macro_input!(attr as syn::LitStr);
+    let args = parse_macro_input!(attr as ShaderArgs);
     let input = parse_macro_input!(item as DeriveInput);
 
-    let mut parser = ShaderParser::new();
-    let fields = if let syn::Data::Struct(data) = input.data {
-        data.fields
-    } else {
-        unimplemented!();
-    };
-    for field in &fields {
-        parser.process_field(field.clone());
+    match expand_shader(args, input) {
+        Ok(expanded) => TokenStream::from(expanded),
+        Err(err) => TokenStream::from(err.to_compile_error()),
     }
-    let shader = Shader {
-        path: attr.value(),
-        vertex_buffers: parser.vertex_buffers,
-        index_buffers: parser.index_buffers,
-        bind_groups: parser.bind_groups,
-        outputs: parser.outputs,
-    };
-    println!("parsed, generating...");
-
-    // Code generation //
-
-    let shader_ident = input.ident.clone();
-    let shader_mod_ident = format_ident!("{}", transform_name(&input.ident.to_string()));
-
-    let mut extra_gen = Vec::<TokenStream2>::new();
-    let mut impl_gen = Vec::<TokenStream2>::new();
-    let mut group_layout_cells = Vec::<TokenStream2>::new();
-    let mut group_layout_cell_defs = Vec::<TokenStream2>::new();
-    let mut group_layout_cell_idents = Vec::<TokenStream2>::new();
-    let mut group_builders = Vec::<TokenStream2>::new();
-    let mut draw_encoder_arg_groups = Vec::<TokenStream2>::new();
-
-    for (group_index, group) in shader.bind_groups.iter().enumerate() {
-        let visibility = match (group.vertex, group.fragment) {
-            (true, false) => quote! { wgpu::ShaderStages::VERTEX },
-            (false, true) => quote! { wgpu::ShaderStages::FRAGMENT},
-            (true, true) => quote! { wgpu::ShaderStages::VERTEX_FRAGMENT },
-            (false, false) => todo!(),
-        };
-        let (group_layout_entry_impls, group_layout_entry_constant_idents): (
-            TokenStream2,
-            TokenStream2,
-        ) = group
-            .entries
-            .iter()
-            .enumerate()
-            .map(|(binding, entry)| {
-                let entry_type = format_ident!("{}", entry.ty);
-                (
-                    entry.group_layout_entry_impl(binding as u32, visibility.clone()),
-                    quote! { #entry_type::LAYOUT_ENTRY, },
-                )
-            })
-            .unzip();
-
-        let descriptor_ident = format_ident!("group_{}_layout_descriptor", group_index);
-        let group_layout_descriptor_static = quote! {
-            pub fn #descriptor_ident() -> wgpu::BindGroupLayoutDescriptor<'static> {
-                wgpu::BindGroupLayoutDescriptor{
-                    label: None,
-                    entries: &[
-                        #group_layout_entry_constant_idents
-                    ]
-                }
-            }
-        };
+}
 
-        let layout_ident = format_ident!("group_{}_layout", group_index);
-        let group_layout_builder = quote! {
-            pub fn #layout_ident(device: &wgpu::Device) -> wgpu::BindGroupLayout {
-                device.create_bind_group_layout(&Self::#descriptor_ident())
-            }
-        };
+/// Implements `VertexProvider` for a `#[repr(C)]` vertex struct, with one attribute per field
+/// at consecutive shader locations. Fields are `f32`, `u32` or `i32`, arrays of two to four of
+/// them, or the matching glam vectors.
+#[proc_macro_derive(Vertex)]
+pub fn derive_vertex(item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as DeriveInput);
 
-        extra_gen.push(quote! {
-            #group_layout_entry_impls
-        });
-        impl_gen.push(quote! {
-            #group_layout_descriptor_static
-            #group_layout_builder
-        });
+    match vertex::expand_derive_vertex(input) {
+        Ok(expanded) => TokenStream::from(expanded),
+        Err(err) => TokenStream::from(err.to_compile_error()),
+    }
+}
 
-        let static_ident = format_ident!("GROUP_{}_LAYOUT", group_index);
-        group_layout_cells.push(quote! {
-            pub static #static_ident: OnceLock<wgpu::BindGroupLayout> = OnceLock::new();
-        });
+/// Generates a compute pipeline for the `cs_main` entry point of a WGSL file, or the one named by
+/// `compute = "..."`, along with the same bind group layouts and builders as `#[shader]`.
+///
+/// Fields are declared as for `#[shader]`, with a bare `#[group]` since every group is visible to
+/// the compute stage. `workgroup_size = (x, y, z)` is optional and checked against the WGSL.
+#[proc_macro_attribute]
+pub fn compute_shader(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(attr as ComputeShaderArgs);
+    let input = parse_macro_input!(item as DeriveInput);
 
-        group_layout_cell_defs.push(quote! {
-            let #layout_ident = #static_ident.get_or_init(|| #shader_ident::#layout_ident(device));
-        });
+    match expand_compute_shader(args, input) {
+        Ok(expanded) => TokenStream::from(expanded),
+        Err(err) => TokenStream::from(err.to_compile_error()),
+    }
+}
 
-        group_layout_cell_idents.push(quote! {
-            #layout_ident,
-        });
+fn expand_shader(args: ShaderArgs, input: DeriveInput) -> syn::Result<TokenStream2> {
+    let source = ShaderSource::load(&args.path, &args.defines)?;
+    let module = source.module()?;
+    let shader_mod_ident = format_ident!("{}", transform_name(&input.ident.to_string()));
 
-        let mut group_builder_args = Vec::<TokenStream2>::new();
-        let mut group_builder_binding_resources = Vec::<TokenStream2>::new();
-        for (entry_index, entry) in group.entries.iter().enumerate() {
-            let arg_ident = format_ident!("entry_{entry_index}");
-            match entry.variant {
-                EntryVariant::Texture2D { .. } => {
-                    group_builder_args.push(quote! {
-                        #arg_ident: &ShaderTexture,
-                    });
-                    group_builder_binding_resources.push(quote! {
-                        wgpu::BindingResource::TextureView(&#arg_ident.view)
-                    });
-                }
-                EntryVariant::Uniform { .. } => {
-                    group_builder_args.push(quote! {
-                        #arg_ident: &ShaderUniform,
-                    });
-                    group_builder_binding_resources.push(quote! {
-                        wgpu::BindingResource::Buffer(#arg_ident.buffer.as_entire_buffer_binding())
-                    });
-                }
-            }
+    let fields = match input.data {
+        syn::Data::Struct(data) => data.fields,
+        _ => {
+            return Err(syn::Error::new(
+                input.ident.span(),
+                "#[shader] can only be applied to structs",
+            ))
         }
-        let group_builder_args = group_builder_args.into_iter().collect::<TokenStream2>();
-        let group_builder_binding_entries = group_builder_binding_resources
-            .into_iter()
-            .enumerate()
-            .map(|(binding, resource)| {
-                let binding = binding as u32;
-                quote! {
-                    wgpu::BindGroupEntry {
-                        binding: #binding,
-                        resource: #resource,
-                    },
-                }
-            })
-            .collect::<TokenStream2>();
+    };
+    let vertex = module.entry_point(
+        &source,
+        ShaderStage::Vertex,
+        args.vertex.as_ref(),
+        VERTEX_ENTRY_POINT,
+    )?;
+    let fragment = match args.vertex_only {
+        true => None,
+        false => Some(module.entry_point(
+            &source,
+            ShaderStage::Fragment,
+            args.fragment.as_ref(),
+            FRAGMENT_ENTRY_POINT,
+        )?),
+    };
+    let pipeline = Pipeline::Render {
+        vertex: vertex.name.clone(),
+        fragment: fragment.map(|fragment| fragment.name.clone()),
+    };
+    let mut shader = if let Some(reflect) = &args.reflect {
+        if let Some(field) = fields.iter().next() {
+            return Err(syn::Error::new(
+                field.span(),
+                "`reflect` derives the interface from the WGSL module, the struct must be empty",
+            ));
+        }
+        Shader::reflect(
+            source,
+            &module,
+            pipeline,
+            reflect,
+            &input.ident,
+            &shader_mod_ident,
+        )?
+    } else {
+        let mut parser = ShaderParser::new();
+        parser.process_fields(&fields)?;
+        Shader {
+            source,
+            pipeline,
+            vertex_buffers: parser.vertex_buffers,
+            index_buffers: parser.index_buffers,
+            bind_groups: parser.bind_groups,
+            outputs: parser.outputs,
+            depth: None,
+            push_constants: parser.push_constants,
+            samples: 1,
+            primitive: Default::default(),
+            generated_types: vec![],
+            span: input.ident.span(),
+        }
+    };
+    shader.samples = args.samples;
+    shader.primitive = args.primitive;
+    // Indexed strips need the restart index of the `#[index]` type.
+    if let (true, None, Some(index)) = (
+        shader.primitive.topology.is_strip(),
+        shader.primitive.strip_index_format,
+        shader.index_buffers.first(),
+    ) {
+        shader.primitive.strip_index_format = Some(index.format());
+    }
+    shader.depth = depth_attr(&input.attrs)?;
+    shader.validate(&module)?;
 
-        let group_builder_ident = format_ident!("group_{group_index}");
-        group_builders.push(quote! {
-            pub fn #group_builder_ident(device: &wgpu::Device, #group_builder_args) -> wgpu::BindGroup {
-                device.create_bind_group(&wgpu::BindGroupDescriptor {
-                    label: None,
-                    layout: #static_ident.get().unwrap(),
-                    entries: &[#group_builder_binding_entries],
-                })
-            }
-        });
+    // Code generation //
 
-        draw_encoder_arg_groups.push(quote! {
-            #group_builder_ident: &'a wgpu::BindGroup,
-        });
-    }
+    let shader_ident = input.ident.clone();
 
     let mut draw_encoder_arg_targets = Vec::<TokenStream2>::new();
     let mut draw_encoder_color_targets = Vec::<TokenStream2>::new();
-    for (output_index, _) in shader.outputs.iter().enumerate() {
+    let mut draw_encoder_format_checks = Vec::<TokenStream2>::new();
+    let mut output_ops_fields = Vec::<TokenStream2>::new();
+    let mut output_ops_defaults = Vec::<TokenStream2>::new();
+    let mut multisampled_textures = Vec::<TokenStream2>::new();
+    for (output_index, output) in shader.outputs.iter().enumerate() {
         let output_ident = format_ident!("output_{output_index}");
         draw_encoder_arg_targets.push(quote! {
-            #output_ident: &'a wgpu::TextureView,
+            #output_ident: &'a dyn OutputTarget,
+        });
+        let format = output.format();
+        let message = format!(
+            "#[output] `{}` was given a view of another format than its color target",
+            output.ident
+        );
+        draw_encoder_format_checks.push(quote! {
+            assert_eq!(#output_ident.format(), #format, #message);
+        });
+        let resolve_target = if shader.samples > 1 {
+            let resolve_ident = format_ident!("resolve_{output_index}");
+            draw_encoder_arg_targets.push(quote! {
+                #resolve_ident: &'a dyn OutputTarget,
+            });
+            let samples_message = format!(
+                "#[output] `{}` needs a multisampled view, see `multisampled_{}`",
+                output.ident, output.ident
+            );
+            let resolve_message = format!(
+                "#[output] `{}` was given a resolve target of another format than its color target",
+                output.ident
+            );
+            draw_encoder_format_checks.push(quote! {
+                assert_eq!(#output_ident.sample_count(), SAMPLES, #samples_message);
+                assert_eq!(#resolve_ident.format(), #format, #resolve_message);
+            });
+            let multisampled_ident = format_ident!("multisampled_{}", output.ident);
+            multisampled_textures.push(quote! {
+                /// A multisampled texture to render this output to before resolving it.
+                pub fn #multisampled_ident(driver: &driver::Driver, width: u32, height: u32) -> ShaderTexture {
+                    ShaderTexture::multisampled(driver, width, height, #format, SAMPLES)
+                }
+            });
+            quote! { Some(#resolve_ident.view()) }
+        } else {
+            quote! { None }
+        };
+        let ops_ident = format_ident!("{}", output.ident);
+        let operations = output.operations();
+        output_ops_fields.push(quote! {
+            pub #ops_ident: wgpu::Operations<wgpu::Color>,
+        });
+        output_ops_defaults.push(quote! {
+            #ops_ident: #operations,
         });
         draw_encoder_color_targets.push(quote! {
             Some(wgpu::RenderPassColorAttachment {
-                view: #output_ident,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Load,
-                    store: wgpu::StoreOp::Store,
-                },
+                view: #output_ident.view(),
+                resolve_target: #resolve_target,
+                ops: ops.#ops_ident,
             }),
         })
     }
-
-    let mut render_pass_binds = Vec::<TokenStream2>::new();
-    for (group_index, _) in shader.bind_groups.iter().enumerate() {
-        let group_ident = format_ident!("group_{}", group_index);
-        let group_index = group_index as u32;
-        render_pass_binds.push(quote! {
-            render_pass.set_bind_group(#group_index, #group_ident, &[]);
-        });
-    }
-
-    let group_layout_cells = group_layout_cells.into_iter().collect::<TokenStream2>();
-    let group_layout_cell_defs = group_layout_cell_defs.into_iter().collect::<TokenStream2>();
-    let group_layout_cell_idents = group_layout_cell_idents
+    let output_ops_fields = output_ops_fields.into_iter().collect::<TokenStream2>();
+    let output_ops_defaults = output_ops_defaults.into_iter().collect::<TokenStream2>();
+    let multisampled_textures = multisampled_textures.into_iter().collect::<TokenStream2>();
+    let samples = shader.samples;
+    let primitive_state = shader.primitive_state();
+    let DeviceChecksGen {
+        required_features,
+        checks: device_checks,
+    } = shader.device_checks_gen(&shader_ident);
+    let PushConstantsGen {
+        ranges: push_constant_ranges,
+        size_assert: push_constants_size_assert,
+        encoder_arg: push_constants_encoder_arg,
+        pass_set: push_constants_pass_set,
+    } = shader
+        .push_constants
+        .as_ref()
+        .map(|push_constants| push_constants.push_constants_gen(module.push_constant_size(), false))
+        .unwrap_or_default();
+    let uniform_size_asserts = shader.uniform_size_asserts(&module);
+
+    let BindGroupsGen {
+        provider_impls,
+        layout_fns,
+        layout_cells,
+        layout_cell_defs,
+        layout_cell_idents,
+        builders,
+        encoder_args,
+        pass_binds,
+    } = shader.bind_groups_gen(&shader_ident);
+    let VertexBuffersGen {
+        attribute_defs: vertex_attribute_defs,
+        layouts: vertex_layouts,
+        encoder_args: vertex_encoder_args,
+        pass_binds: vertex_pass_binds,
+    } = shader.vertex_buffers_gen();
+    let draw_encoder_arg_targets = draw_encoder_arg_targets
         .into_iter()
         .collect::<TokenStream2>();
-    let extra_gen = extra_gen.into_iter().collect::<TokenStream2>();
-    let impl_gen = impl_gen.into_iter().collect::<TokenStream2>();
-    let group_builders = group_builders.into_iter().collect::<TokenStream2>();
-    let draw_encoder_arg_groups = draw_encoder_arg_groups
+    let draw_encoder_color_targets = draw_encoder_color_targets
         .into_iter()
         .collect::<TokenStream2>();
-    let draw_encoder_arg_targets = draw_encoder_arg_targets
-        .into_iter()
+    let color_target_states = shader
+        .outputs
+        .iter()
+        .map(|output| output.color_target_state())
         .collect::<TokenStream2>();
-    let draw_encoder_color_targets = draw_encoder_color_targets
+    let draw_encoder_format_checks = draw_encoder_format_checks
         .into_iter()
         .collect::<TokenStream2>();
-    let render_pass_binds = render_pass_binds.into_iter().collect::<TokenStream2>();
+    let depth = shader.depth.as_ref();
+    let depth_stencil_state =
+        depth.map_or_else(|| quote! { None }, |depth| depth.depth_stencil_state());
+    let depth_stencil_attachment =
+        depth.map_or_else(|| quote! { None }, |depth| depth.attachment());
+    let depth_encoder_arg = depth.map(|_| quote! { depth: &'a DepthTexture, });
+    let depth_format_check = depth.map(|_| quote! {
+        assert_eq!(depth.format(), DEPTH_FORMAT, "#[depth] was given a texture of another format");
+        assert_eq!(depth.texture.sample_count(), SAMPLES, "#[depth] was given a texture of another sample count");
+    });
+    let stencil_reference = depth
+        .and_then(|depth| depth.stencil.as_ref())
+        .map(|stencil| {
+            let reference = &stencil.reference;
+            quote! { pass.set_stencil_reference(#reference); }
+        });
+    let depth_texture = depth.map(|depth| {
+        let format = &depth.format;
+        quote! {
+            pub const DEPTH_FORMAT: wgpu::TextureFormat = #format;
+
+            /// A `#[depth]` attachment the size of `window`, recreate it when the window resizes.
+            pub fn depth_texture(device: &wgpu::Device, window: &bedrock_lib::winit::window::Window) -> DepthTexture {
+                DepthTexture::new(device, window, DEPTH_FORMAT, SAMPLES)
+            }
+        }
+    });
+    let begin_pass = quote! {
+        #draw_encoder_format_checks
+        #depth_format_check
+        let ops = ops.unwrap_or_default();
+        let pipeline = PIPELINE.get().unwrap();
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: None,
+        });
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[#draw_encoder_color_targets],
+            depth_stencil_attachment: #depth_stencil_attachment,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        pass.set_pipeline(pipeline);
+        #stencil_reference
+        #push_constants_pass_set
+        #vertex_pass_binds
+        #pass_binds
+    };
+    let draw_indexed_encoder = shader.index_buffers.first().map(|index| {
+        let IndexBufferGen {
+            encoder_args: index_encoder_args,
+            pass_bind: index_pass_bind,
+        } = index.index_buffer_gen();
+        quote! {
+            pub fn draw_indexed_encoder<'a>(device: &wgpu::Device, #index_encoder_args instances: Range<u32>, #vertex_encoder_args #encoder_args #push_constants_encoder_arg #draw_encoder_arg_targets #depth_encoder_arg ops: Option<OutputOps>) -> wgpu::CommandEncoder {
+                #begin_pass
+                #index_pass_bind
+                pass.draw_indexed(indices, base_vertex, instances);
+
+                drop(pass);
+                encoder
+            }
+        }
+    });
+    let source_paths = shader
+        .source
+        .files
+        .iter()
+        .map(|file| file.path.to_string_lossy().into_owned());
+    let wgsl = &shader.source.source;
+    let Pipeline::Render {
+        vertex: vertex_entry_point,
+        fragment: fragment_entry_point,
+    } = &shader.pipeline
+    else {
+        unreachable!("#[shader] builds render pipelines")
+    };
+    let fragment_state = match fragment_entry_point {
+        Some(entry_point) => quote! {
+            Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: #entry_point,
+                targets: &[#color_target_states],
+            })
+        },
+        None => quote! { None },
+    };
+    let generated_types = shader
+        .generated_types
+        .iter()
+        .map(|ty| quote! { pub struct #ty {} })
+        .collect::<TokenStream2>();
 
     let expanded = quote! {
-        #extra_gen
+        #provider_impls
         struct #shader_ident {}
         impl #shader_ident {
-            #impl_gen
+            #layout_fns
         }
         mod #shader_mod_ident {
             use std::{sync::OnceLock, borrow::Cow, ops::Range};
 
-            use bedrock_lib::{ShaderTexture, ShaderUniform, driver};
+            use bedrock_lib::{ShaderSampler, ShaderStorage, ShaderTexture, ShaderUniform, UniformRing, DepthTexture, IndexBuffer, InstanceBuffer, OutputTarget, VertexBuffer, driver};
+
+            use super::*;
+
+            // Rebuilds when the WGSL files change, the preprocessed source is embedded below.
+            #(const _: &str = include_str!(#source_paths);)*
 
-            use super::#shader_ident;
+            #generated_types
 
-            #group_layout_cells
+            #layout_cells
 
             pub static GROUP_LAYOUTS: OnceLock<Vec<&'static wgpu::BindGroupLayout>> = OnceLock::new();
             pub static PIPELINE_LAYOUT_DESCRIPTOR: OnceLock<wgpu::PipelineLayoutDescriptor> = OnceLock::new();
             pub static PIPELINE: OnceLock<wgpu::RenderPipeline> = OnceLock::new();
 
-            #group_builders
+            #builders
+
+            /// Load and store ops of each `#[output]`, passed to `draw_encoder` to override the
+            /// declared ones.
+            #[derive(Clone, Copy, Debug)]
+            pub struct OutputOps {
+                #output_ops_fields
+            }
+
+            impl Default for OutputOps {
+                fn default() -> Self {
+                    Self {
+                        #output_ops_defaults
+                    }
+                }
+            }
+
+            /// MSAA sample count of the pipeline and every attachment.
+            pub const SAMPLES: u32 = #samples;
+
+            #multisampled_textures
+
+            #depth_texture
+
+            #required_features
+
+            #uniform_size_asserts
+            #push_constants_size_assert
+            const PUSH_CONSTANT_RANGES: &[wgpu::PushConstantRange] = &[#push_constant_ranges];
 
             pub fn pipeline(device: &wgpu::Device) -> &wgpu::RenderPipeline {
-                #group_layout_cell_defs
-                let group_layouts = GROUP_LAYOUTS.get_or_init(|| vec![#group_layout_cell_idents]);
+                #device_checks
+
+                #layout_cell_defs
+                let group_layouts = GROUP_LAYOUTS.get_or_init(|| vec![#layout_cell_idents]);
 
                 let pipeline_layout_descriptor = PIPELINE_LAYOUT_DESCRIPTOR.get_or_init(||
                     wgpu::PipelineLayoutDescriptor {
                             label: None,
                             bind_group_layouts: group_layouts,
-                            push_constant_ranges: &[],
+                            push_constant_ranges: PUSH_CONSTANT_RANGES,
                     });
                 let pipeline_layout = device.create_pipeline_layout(pipeline_layout_descriptor);
 
                 let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
                     label: None,
-                    source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shader.wgsl"))),
+                    source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(#wgsl)),
                 });
 
+                #vertex_attribute_defs
+
                 PIPELINE.get_or_init(|| {
                     device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
                         label: None,
                         layout: Some(&pipeline_layout),
                         vertex: wgpu::VertexState {
                             module: &shader,
-                            entry_point: "vs_main",
-                            buffers: &[],
+                            entry_point: #vertex_entry_point,
+                            buffers: &[#vertex_layouts],
+                        },
+                        fragment: #fragment_state,
+                        primitive: #primitive_state,
+                        depth_stencil: #depth_stencil_state,
+                        multisample: wgpu::MultisampleState {
+                            count: SAMPLES,
+                            ..Default::default()
                         },
-                        fragment: Some(wgpu::FragmentState {
-                            module: &shader,
-                            entry_point: "fs_main",
-                            targets: &[Some(wgpu::ColorTargetState::from(*(driver::SWAP_CHAIN_FORMAT.get().unwrap()))), Some(wgpu::ColorTargetState::from(*(driver::SWAP_CHAIN_FORMAT.get().unwrap())))]
-                        }),
-                        primitive: wgpu::PrimitiveState::default(),
-                        depth_stencil: None,
-                        multisample: wgpu::MultisampleState::default(),
                         multiview: None,
                     })
                 })
             }
 
-            pub fn draw_encoder<'a>(device: &wgpu::Device, vertices: Range<u32>, instances: Range<u32>, #draw_encoder_arg_groups #draw_encoder_arg_targets) -> wgpu::CommandEncoder {
+            pub fn draw_encoder<'a>(device: &wgpu::Device, vertices: Range<u32>, instances: Range<u32>, #vertex_encoder_args #encoder_args #push_constants_encoder_arg #draw_encoder_arg_targets #depth_encoder_arg ops: Option<OutputOps>) -> wgpu::CommandEncoder {
+                #begin_pass
+                pass.draw(vertices, instances);
+
+                drop(pass);
+                encoder
+            }
+
+            #draw_indexed_encoder
+        }
+    };
+
+    Ok(expanded)
+}
+
+fn expand_compute_shader(args: ComputeShaderArgs, input: DeriveInput) -> syn::Result<TokenStream2> {
+    let source = ShaderSource::load(&args.path, &args.defines)?;
+    let module = source.module()?;
+    let shader_mod_ident = format_ident!("{}", transform_name(&input.ident.to_string()));
+
+    let fields = match input.data {
+        syn::Data::Struct(data) => data.fields,
+        _ => {
+            return Err(syn::Error::new(
+                input.ident.span(),
+                "#[compute_shader] can only be applied to structs",
+            ))
+        }
+    };
+    let entry_point = module.entry_point(
+        &source,
+        ShaderStage::Compute,
+        args.compute.as_ref(),
+        COMPUTE_ENTRY_POINT,
+    )?;
+    let workgroup_size = entry_point.workgroup_size;
+    if let Some((span, size)) = args.workgroup_size {
+        if size != workgroup_size {
+            return Err(syn::Error::new(
+                span,
+                format!(
+                    "`{}` declares @workgroup_size({}, {}, {})",
+                    entry_point.name, workgroup_size[0], workgroup_size[1], workgroup_size[2]
+                ),
+            ));
+        }
+    }
+
+    if let Some(attr) = input.attrs.iter().find(|attr| attr.path.is_ident("depth")) {
+        return Err(syn::Error::new(
+            attr.path.span(),
+            "#[depth] is only supported by render pipelines",
+        ));
+    }
+    let mut parser = ShaderParser::compute();
+    parser.process_fields(&fields)?;
+    let shader = Shader {
+        source,
+        pipeline: Pipeline::Compute {
+            entry_point: entry_point.name.clone(),
+            workgroup_size,
+        },
+        vertex_buffers: vec![],
+        index_buffers: vec![],
+        bind_groups: parser.bind_groups,
+        outputs: vec![],
+        depth: None,
+        push_constants: parser.push_constants,
+        samples: 1,
+        primitive: Default::default(),
+        generated_types: vec![],
+        span: input.ident.span(),
+    };
+    shader.validate(&module)?;
+
+    // Code generation //
+
+    let shader_ident = input.ident.clone();
+    let BindGroupsGen {
+        provider_impls,
+        layout_fns,
+        layout_cells,
+        layout_cell_defs,
+        layout_cell_idents,
+        builders,
+        encoder_args,
+        pass_binds,
+    } = shader.bind_groups_gen(&shader_ident);
+    let DeviceChecksGen {
+        required_features,
+        checks: device_checks,
+    } = shader.device_checks_gen(&shader_ident);
+    let PushConstantsGen {
+        ranges: push_constant_ranges,
+        size_assert: push_constants_size_assert,
+        encoder_arg: push_constants_encoder_arg,
+        pass_set: push_constants_pass_set,
+    } = shader
+        .push_constants
+        .as_ref()
+        .map(|push_constants| push_constants.push_constants_gen(module.push_constant_size(), true))
+        .unwrap_or_default();
+    let uniform_size_asserts = shader.uniform_size_asserts(&module);
+    let source_paths = shader
+        .source
+        .files
+        .iter()
+        .map(|file| file.path.to_string_lossy().into_owned());
+    let wgsl = &shader.source.source;
+    let [size_x, size_y, size_z] = workgroup_size;
+    let compute_entry_point = &entry_point.name;
+
+    let expanded = quote! {
+        #provider_impls
+        struct #shader_ident {}
+        impl #shader_ident {
+            #layout_fns
+        }
+        mod #shader_mod_ident {
+            use std::{sync::OnceLock, borrow::Cow};
+
+            use bedrock_lib::{ShaderSampler, ShaderStorage, ShaderTexture, ShaderUniform, UniformRing};
+
+            use super::*;
+
+            // Rebuilds when the WGSL files change, the preprocessed source is embedded below.
+            #(const _: &str = include_str!(#source_paths);)*
+
+            /// The `@workgroup_size` of the compute entry point.
+            pub const WORKGROUP_SIZE: [u32; 3] = [#size_x, #size_y, #size_z];
+
+            #layout_cells
+
+            pub static GROUP_LAYOUTS: OnceLock<Vec<&'static wgpu::BindGroupLayout>> = OnceLock::new();
+            pub static PIPELINE_LAYOUT_DESCRIPTOR: OnceLock<wgpu::PipelineLayoutDescriptor> = OnceLock::new();
+            pub static PIPELINE: OnceLock<wgpu::ComputePipeline> = OnceLock::new();
+
+            #builders
+
+            #required_features
+
+            #uniform_size_asserts
+            #push_constants_size_assert
+            const PUSH_CONSTANT_RANGES: &[wgpu::PushConstantRange] = &[#push_constant_ranges];
+
+            pub fn pipeline(device: &wgpu::Device) -> &wgpu::ComputePipeline {
+                #device_checks
+
+                #layout_cell_defs
+                let group_layouts = GROUP_LAYOUTS.get_or_init(|| vec![#layout_cell_idents]);
+
+                let pipeline_layout_descriptor = PIPELINE_LAYOUT_DESCRIPTOR.get_or_init(||
+                    wgpu::PipelineLayoutDescriptor {
+                            label: None,
+                            bind_group_layouts: group_layouts,
+                            push_constant_ranges: PUSH_CONSTANT_RANGES,
+                    });
+                let pipeline_layout = device.create_pipeline_layout(pipeline_layout_descriptor);
+
+                let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                    label: None,
+                    source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(#wgsl)),
+                });
+
+                PIPELINE.get_or_init(|| {
+                    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                        label: None,
+                        layout: Some(&pipeline_layout),
+                        module: &shader,
+                        entry_point: #compute_entry_point,
+                    })
+                })
+            }
+
+            /// Workgroups needed for one invocation per element of a `size` grid.
+            pub fn workgroups(size: [u32; 3]) -> [u32; 3] {
+                [
+                    size[0].div_ceil(WORKGROUP_SIZE[0]),
+                    size[1].div_ceil(WORKGROUP_SIZE[1]),
+                    size[2].div_ceil(WORKGROUP_SIZE[2]),
+                ]
+            }
+
+            /// Workgroups needed for one invocation per texel of `T`.
+            pub fn texture_workgroups<T: bedrock_lib::TextureProvider>() -> [u32; 3] {
+                workgroups([T::WIDTH, T::HEIGHT, T::DEPTH_OR_ARRAY_LAYERS])
+            }
+
+            pub fn dispatch_encoder<'a>(device: &wgpu::Device, workgroups: [u32; 3], #encoder_args #push_constants_encoder_arg) -> wgpu::CommandEncoder {
                 let pipeline = PIPELINE.get().unwrap();
                 let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
                     label: None,
                 });
-                let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
                     label: None,
-                    color_attachments: &[#draw_encoder_color_targets],
-                    depth_stencil_attachment: None,
                     timestamp_writes: None,
-                    occlusion_query_set: None,
                 });
-                render_pass.set_pipeline(pipeline);
-                #render_pass_binds
-                render_pass.draw(vertices, instances);
+                pass.set_pipeline(pipeline);
+                #push_constants_pass_set
+                #pass_binds
+                pass.dispatch_workgroups(workgroups[0], workgroups[1], workgroups[2]);
 
-                drop(render_pass);
+                drop(pass);
                 encoder
             }
         }
     };
 
-    TokenStream::from(expanded)
+    Ok(expanded)
 }
 
 // -----------------------------------------------------------------------------------------------
@@ -331,3 +714,17 @@ fn transform_name(name: &str) -> String {
 
     transformed_name
 }
+
+/// `snake_case` to `CamelCase`, e.g. for WGSL globals and `wgpu` enum variants.
+fn camel_case(name: &str) -> String {
+    name.split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                .unwrap_or_default()
+        })
+        .collect()
+}
@@ -1,117 +1,1016 @@
-use std::collections::HashMap;
-
-use nom::{
-    bytes::complete::{tag, take_till, take_until},
-    character::complete::alphanumeric0,
-    multi::separated_list0,
-    sequence::separated_pair,
-    IResult,
+use proc_macro2::Span;
+use quote::{format_ident, ToTokens};
+use syn::{
+    parse::{Parse, ParseStream},
+    parse_quote,
+    spanned::Spanned,
+    Attribute, BinOp, Expr, ExprBinary, ExprCall, Ident, LitStr, Token,
 };
-use proc_macro2::TokenStream as TokenStream2;
-use quote::ToTokens;
-use syn::Attribute;
+use wgpu::{
+    Face, FrontFace, IndexFormat, PolygonMode, PrimitiveState, PrimitiveTopology, TextureSampleType,
+};
+
+use crate::{
+    args::{ArgValue, Args},
+    camel_case,
+    errors::Errors,
+    format::Format,
+    schema::{
+        Depth, Entry, EntryVariant, Group, Index, Output, PushConstants, Stencil, TextureKind,
+        Vertex, Visibility,
+    },
+};
+
+/// Arguments of the `#[shader("path.wgsl", ...)]` attribute itself.
+pub struct ShaderArgs {
+    pub path: LitStr,
+    pub reflect: Option<ReflectArgs>,
+    /// MSAA sample count, 1 without multisampling.
+    pub samples: u32,
+    pub primitive: PrimitiveState,
+    /// Entry point names overriding `vs_main` and `fs_main`.
+    pub vertex: Option<LitStr>,
+    pub fragment: Option<LitStr>,
+    /// The pipeline has no fragment stage.
+    pub vertex_only: bool,
+    /// Names set for the WGSL preprocessor, with their substituted values.
+    pub defines: Vec<(String, String)>,
+}
+
+/// `reflect` or `reflect(width = .., height = ..)`: derive the interface from the WGSL module.
+pub struct ReflectArgs {
+    pub span: Span,
+    pub width: Option<Expr>,
+    pub height: Option<Expr>,
+}
+
+impl Parse for ShaderArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let path: LitStr = input.parse()?;
+        if !input.is_empty() {
+            input.parse::<Token![,]>()?;
+        }
+        let mut args = Args::parse_list(path.span(), input)?;
+
+        let reflect = match args.list("reflect")? {
+            Some(mut reflect) => {
+                let reflect_args = ReflectArgs {
+                    span: reflect.span,
+                    width: reflect.expr("width")?,
+                    height: reflect.expr("height")?,
+                };
+                reflect.finish(&["width", "height"])?;
+                Some(reflect_args)
+            }
+            None => None,
+        };
+        let samples = match args.expr("samples")? {
+            Some(expr) => sample_count(&expr)?,
+            None => 1,
+        };
+        let primitive = primitive_state(&mut args)?;
+        let vertex = args.lit_str("vertex")?;
+        let fragment = args.lit_str("fragment")?;
+        let vertex_only = args.flag("vertex_only")?;
+        let defines = defines(&mut args)?;
+        if let (true, Some(fragment)) = (vertex_only, &fragment) {
+            return Err(syn::Error::new(
+                fragment.span(),
+                "`vertex_only` pipelines have no fragment entry point",
+            ));
+        }
+        args.finish(&[
+            "reflect",
+            "vertex",
+            "fragment",
+            "vertex_only",
+            "defines",
+            "samples",
+            "topology",
+            "strip_index_format",
+            "front_face",
+            "cull_mode",
+            "polygon_mode",
+            "unclipped_depth",
+        ])?;
+
+        Ok(Self {
+            path,
+            reflect,
+            samples,
+            primitive,
+            vertex,
+            fragment,
+            vertex_only,
+            defines,
+        })
+    }
+}
 
-use crate::{Entry, EntryVariant, Group, Index, Output, Vertex};
+/// The `topology`, `strip_index_format`, `front_face`, `cull_mode`, `polygon_mode` and
+/// `unclipped_depth` options, defaulting to `PrimitiveState::default()`.
+fn primitive_state(args: &mut Args) -> syn::Result<PrimitiveState> {
+    let topology = args.choice(
+        "topology",
+        &[
+            "point_list",
+            "line_list",
+            "line_strip",
+            "triangle_list",
+            "triangle_strip",
+        ],
+    )?;
+    let strip_index_format = args.choice("strip_index_format", &["u16", "u32"])?;
+    let front_face = args.choice("front_face", &["ccw", "cw"])?;
+    let cull_mode = args.choice("cull_mode", &["none", "front", "back"])?;
+    let polygon_mode = args.choice("polygon_mode", &["fill", "line", "point"])?;
+    let unclipped_depth = args.bool("unclipped_depth")?.unwrap_or(false);
+
+    let topology = match topology.as_ref().map(Ident::to_string).as_deref() {
+        Some("point_list") => PrimitiveTopology::PointList,
+        Some("line_list") => PrimitiveTopology::LineList,
+        Some("line_strip") => PrimitiveTopology::LineStrip,
+        Some("triangle_strip") => PrimitiveTopology::TriangleStrip,
+        _ => PrimitiveTopology::TriangleList,
+    };
+    let strip_index_format = match strip_index_format {
+        Some(format) if !topology.is_strip() => {
+            return Err(syn::Error::new(
+                format.span(),
+                "`strip_index_format` needs a `line_strip` or `triangle_strip` topology",
+            ))
+        }
+        Some(format) if format == "u16" => Some(IndexFormat::Uint16),
+        Some(_) => Some(IndexFormat::Uint32),
+        None => None,
+    };
+    Ok(PrimitiveState {
+        topology,
+        strip_index_format,
+        front_face: match front_face {
+            Some(front_face) if front_face == "cw" => FrontFace::Cw,
+            _ => FrontFace::Ccw,
+        },
+        cull_mode: match cull_mode.as_ref().map(Ident::to_string).as_deref() {
+            Some("front") => Some(Face::Front),
+            Some("back") => Some(Face::Back),
+            _ => None,
+        },
+        unclipped_depth,
+        polygon_mode: match polygon_mode.as_ref().map(Ident::to_string).as_deref() {
+            Some("line") => PolygonMode::Line,
+            Some("point") => PolygonMode::Point,
+            _ => PolygonMode::Fill,
+        },
+        conservative: false,
+    })
+}
+
+/// `samples = 4`, one of the sample counts wgpu can support.
+fn sample_count(expr: &Expr) -> syn::Result<u32> {
+    if let Expr::Lit(syn::ExprLit {
+        lit: syn::Lit::Int(int),
+        ..
+    }) = expr
+    {
+        let samples = int.base10_parse()?;
+        if [1, 2, 4, 8, 16].contains(&samples) {
+            return Ok(samples);
+        }
+    }
+    Err(syn::Error::new(
+        expr.span(),
+        "expected `samples` to be 1, 2, 4, 8 or 16",
+    ))
+}
+
+/// Arguments of `#[compute_shader("path.wgsl", workgroup_size = ..)]`.
+pub struct ComputeShaderArgs {
+    pub path: LitStr,
+    /// Entry point name overriding `cs_main`.
+    pub compute: Option<LitStr>,
+    /// Names set for the WGSL preprocessor, with their substituted values.
+    pub defines: Vec<(String, String)>,
+    /// Checked against the WGSL entry point's `@workgroup_size` when given.
+    pub workgroup_size: Option<(Span, [u32; 3])>,
+}
+
+impl Parse for ComputeShaderArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let path: LitStr = input.parse()?;
+        if !input.is_empty() {
+            input.parse::<Token![,]>()?;
+        }
+        let mut args = Args::parse_list(path.span(), input)?;
+        let compute = args.lit_str("compute")?;
+        let defines = defines(&mut args)?;
+        let workgroup_size = args
+            .expr("workgroup_size")?
+            .map(|expr| Ok::<_, syn::Error>((expr.span(), workgroup_size(&expr)?)))
+            .transpose()?;
+        args.finish(&["compute", "defines", "workgroup_size"])?;
+
+        Ok(Self {
+            path,
+            compute,
+            defines,
+            workgroup_size,
+        })
+    }
+}
+
+/// `defines(NAME, NAME = value, ..)`. Values are literals or expressions spliced into the WGSL as
+/// written, string literals are spliced without their quotes.
+fn defines(args: &mut Args) -> syn::Result<Vec<(String, String)>> {
+    let Some(list) = args.list("defines")? else {
+        return Ok(vec![]);
+    };
+    list.into_args()
+        .into_iter()
+        .map(|arg| {
+            let value = match arg.value {
+                ArgValue::Flag => String::new(),
+                ArgValue::Expr(expr) => match *expr {
+                    Expr::Lit(syn::ExprLit {
+                        lit: syn::Lit::Str(value),
+                        ..
+                    }) => value.value(),
+                    expr => expr.to_token_stream().to_string(),
+                },
+                ArgValue::List(_) => {
+                    return Err(syn::Error::new(
+                        arg.key.span(),
+                        format!("expected `{}` or `{} = value`", arg.key, arg.key),
+                    ))
+                }
+            };
+            Ok((arg.key.to_string(), value))
+        })
+        .collect()
+}
+
+/// `64`, `(8, 8)` or `[4, 4, 4]`, missing dimensions defaulting to 1 like in WGSL.
+fn workgroup_size(expr: &Expr) -> syn::Result<[u32; 3]> {
+    let dimensions = match expr {
+        Expr::Tuple(tuple) => tuple.elems.iter().collect::<Vec<_>>(),
+        Expr::Array(array) => array.elems.iter().collect(),
+        Expr::Paren(paren) => vec![&*paren.expr],
+        expr => vec![expr],
+    };
+    let invalid = || {
+        syn::Error::new(
+            expr.span(),
+            "expected `workgroup_size` to be up to three integer literals, e.g. `(8, 8)`",
+        )
+    };
+    if dimensions.is_empty() || dimensions.len() > 3 {
+        return Err(invalid());
+    }
+    let mut size = [1; 3];
+    for (dimension, expr) in size.iter_mut().zip(dimensions) {
+        let Expr::Lit(syn::ExprLit {
+            lit: syn::Lit::Int(int),
+            ..
+        }) = expr
+        else {
+            return Err(invalid());
+        };
+        *dimension = int.base10_parse()?;
+    }
+    Ok(size)
+}
+
+const COMPARE_FUNCTIONS: &[&str] = &[
+    "never",
+    "less",
+    "equal",
+    "less_equal",
+    "greater",
+    "not_equal",
+    "greater_equal",
+    "always",
+];
+
+const STENCIL_OPERATIONS: &[&str] = &[
+    "keep",
+    "zero",
+    "replace",
+    "invert",
+    "increment_clamp",
+    "decrement_clamp",
+    "increment_wrap",
+    "decrement_wrap",
+];
+
+/// Parses the struct-level `#[depth(format = .., compare = .., write = .., clear = ..,
+/// stencil(..))]` attribute, if there is one.
+pub fn depth_attr(attrs: &[Attribute]) -> syn::Result<Option<Depth>> {
+    let mut depth_attrs = attrs.iter().filter(|attr| attr.path.is_ident("depth"));
+    let Some(attr) = depth_attrs.next() else {
+        return Ok(None);
+    };
+    if let Some(duplicate) = depth_attrs.next() {
+        return Err(syn::Error::new(
+            duplicate.path.span(),
+            "duplicate #[depth] attribute",
+        ));
+    }
+    let mut args = Args::from_attr(attr)?;
+    let format = match args.ident("format")? {
+        Some(format) => Format::parse(format)?,
+        None => Format::parse(format_ident!("Depth32Float", span = attr.path.span()))?,
+    };
+    let compare = variant_choice(&mut args, "compare", COMPARE_FUNCTIONS)?;
+    let write = args.bool("write")?.unwrap_or(true);
+    let clear = args.expr("clear")?;
+    let stencil = match args.list("stencil")? {
+        Some(mut stencil_args) => {
+            let span = stencil_args.span;
+            let stencil = Stencil {
+                compare: variant_choice(&mut stencil_args, "compare", COMPARE_FUNCTIONS)?
+                    .unwrap_or_else(|| format_ident!("Always")),
+                fail: stencil_op(&mut stencil_args, "fail")?,
+                depth_fail: stencil_op(&mut stencil_args, "depth_fail")?,
+                pass: stencil_op(&mut stencil_args, "pass")?,
+                read_mask: stencil_args
+                    .expr("read_mask")?
+                    .unwrap_or_else(|| parse_quote!(0xff)),
+                write_mask: stencil_args
+                    .expr("write_mask")?
+                    .unwrap_or_else(|| parse_quote!(0xff)),
+                reference: stencil_args
+                    .expr("reference")?
+                    .unwrap_or_else(|| parse_quote!(0)),
+                clear: stencil_args.expr("clear")?,
+            };
+            stencil_args.finish(&[
+                "compare",
+                "fail",
+                "depth_fail",
+                "pass",
+                "read_mask",
+                "write_mask",
+                "reference",
+                "clear",
+            ])?;
+            if !format.format.has_stencil_aspect() {
+                return Err(syn::Error::new(
+                    span,
+                    format!(
+                        "`{}` has no stencil aspect, use e.g. `Depth24PlusStencil8`",
+                        format.ident
+                    ),
+                ));
+            }
+            Some(stencil)
+        }
+        None => None,
+    };
+    args.finish(&["format", "compare", "write", "clear", "stencil"])?;
+    if !format.format.has_depth_aspect() {
+        return Err(syn::Error::new(
+            format.ident.span(),
+            format!("`{}` is not a depth format", format.ident),
+        ));
+    }
+    Ok(Some(Depth {
+        format,
+        compare: compare.unwrap_or_else(|| format_ident!("Less")),
+        write,
+        clear,
+        stencil,
+    }))
+}
+
+fn stencil_op(args: &mut Args, key: &str) -> syn::Result<Ident> {
+    Ok(variant_choice(args, key, STENCIL_OPERATIONS)?.unwrap_or_else(|| format_ident!("Keep")))
+}
+
+/// `key = Variant` naming a `wgpu` enum variant out of `choices`, given in snake case, like
+/// `format` takes `wgpu::TextureFormat` names. The snake case `key = variant` is accepted too.
+fn variant_choice(args: &mut Args, key: &str, choices: &[&str]) -> syn::Result<Option<Ident>> {
+    let Some(ident) = args.ident(key)? else {
+        return Ok(None);
+    };
+    let variants = choices
+        .iter()
+        .map(|choice| camel_case(choice))
+        .collect::<Vec<_>>();
+    if !variants.iter().any(|variant| ident == variant)
+        && !choices.iter().any(|choice| ident == choice)
+    {
+        return Err(syn::Error::new(
+            ident.span(),
+            format!("expected `{}` to be one of: {}", key, variants.join(", ")),
+        ));
+    }
+    Ok(Some(wgpu_variant(ident)))
+}
+
+/// Options every bind group entry attribute takes besides its own.
+const ENTRY_OPTIONS: &[&str] = &["binding", "visibility"];
+
+/// `binding = N` and `visibility = ..` of an entry, both defaulting from its group.
+struct EntryOptions {
+    binding: Option<(Span, u32)>,
+    visibility: Option<Visibility>,
+}
 
 #[derive(Debug)]
 pub struct ShaderParser {
+    /// Compute pipelines have no vertex or fragment stage, groups are visible to `compute`.
+    pub compute: bool,
     pub vertex_buffers: Vec<Vertex>,
     pub index_buffers: Vec<Index>,
     pub bind_groups: Vec<Group>,
     pub outputs: Vec<Output>,
+    pub push_constants: Option<PushConstants>,
 }
 
 impl ShaderParser {
     pub fn new() -> Self {
         Self {
+            compute: false,
             vertex_buffers: vec![],
             index_buffers: vec![],
             bind_groups: vec![],
             outputs: vec![],
+            push_constants: None,
+        }
+    }
+
+    pub fn compute() -> Self {
+        Self {
+            compute: true,
+            ..Self::new()
+        }
+    }
+
+    /// Processes every field, reporting all malformed attributes at once.
+    pub fn process_fields<'a>(
+        &mut self,
+        fields: impl IntoIterator<Item = &'a syn::Field>,
+    ) -> syn::Result<()> {
+        let mut errors = Errors::default();
+        for field in fields {
+            if let Err(err) = self.process_field(field) {
+                errors.extend(err);
+            }
         }
+        self.bind_groups.sort_by_key(|group| group.index);
+        for group in &mut self.bind_groups {
+            group.entries.sort_by_key(|entry| entry.binding);
+        }
+        errors.finish()
     }
 
-    pub fn process_field(&mut self, field: syn::Field) {
+    pub fn process_field(&mut self, field: &syn::Field) -> syn::Result<()> {
         if field.attrs.is_empty() {
-            return;
+            return Ok(());
         }
-        let field_name = field.ident.as_ref().unwrap().to_string();
-        let field_type = field.ty.to_token_stream();
+        let field_ident = field.ident.as_ref().ok_or_else(|| {
+            syn::Error::new(field.span(), "#[shader] structs must have named fields")
+        })?;
         for attr in &field.attrs {
-            self.process_field_attr(attr.clone(), &field_name, field_type.clone());
+            self.process_field_attr(attr, field_ident, &field.ty)?;
         }
+        Ok(())
     }
 
     pub fn process_field_attr(
         &mut self,
-        attr: Attribute,
-        field_name: &str,
-        field_type: TokenStream2,
-    ) {
-        let ident = attr.path.get_ident().unwrap().to_string();
-        let inner = attr.tokens.to_string();
+        attr: &Attribute,
+        field_ident: &Ident,
+        field_type: &syn::Type,
+    ) -> syn::Result<()> {
+        let Some(ident) = attr.path.get_ident().map(Ident::to_string) else {
+            return Err(syn::Error::new(
+                attr.path.span(),
+                "unknown #[shader] field attribute",
+            ));
+        };
+        if ident == "doc" {
+            return Ok(());
+        }
+        let mut args = Args::from_attr(attr)?;
         match ident.as_str() {
-            "texture2d" => {
-                let args = inner_args(&inner);
-                self.bind_groups.last_mut().unwrap().entries.push(Entry {
-                    ident: field_name.to_string(),
-                    ty: field_type.to_string(),
-                    variant: EntryVariant::Texture2D {
-                        width: args.get("width").unwrap().parse().unwrap(),
-                        height: args.get("height").unwrap().parse().unwrap(),
-                    },
-                })
+            "texture2d" | "texture2d_array" | "texture3d" | "texture_cube" => {
+                let options = Self::entry_options(&mut args)?;
+                let variant = texture_variant(&ident, args)?;
+                self.push_entry(attr, field_ident, field_type, options, variant)?;
+            }
+            "storage_texture" => {
+                let options = Self::entry_options(&mut args)?;
+                let width = args.required_expr("width")?;
+                let height = args.required_expr("height")?;
+                let format = Format::parse(args.required_ident("format")?)?;
+                let access = wgpu_variant(
+                    args.required_choice("access", &["write_only", "read_only", "read_write"])?,
+                );
+                args.finish(&[&["width", "height", "format", "access"], ENTRY_OPTIONS].concat())?;
+                if format.block_size().is_none() {
+                    return Err(syn::Error::new(
+                        format.ident.span(),
+                        format!("`{}` can't back a #[storage_texture]", format.ident),
+                    ));
+                }
+                let variant = EntryVariant::StorageTexture {
+                    width: Box::new(width),
+                    height: Box::new(height),
+                    format,
+                    access,
+                };
+                self.push_entry(attr, field_ident, field_type, options, variant)?;
             }
             "uniform" => {
-                let args = inner_args(&inner);
-                self.bind_groups.last_mut().unwrap().entries.push(Entry {
-                    ident: field_name.to_string(),
-                    ty: field_type.to_string(),
-                    variant: EntryVariant::Uniform {
-                        size: args.get("size").unwrap().parse().unwrap(),
-                    },
+                let options = Self::entry_options(&mut args)?;
+                let variant = EntryVariant::Uniform {
+                    size: args.expr("size")?,
+                    dynamic: args.flag("dynamic")?,
+                };
+                args.finish(&[&["size", "dynamic"], ENTRY_OPTIONS].concat())?;
+                self.push_entry(attr, field_ident, field_type, options, variant)?;
+            }
+            "storage" => {
+                let options = Self::entry_options(&mut args)?;
+                let read = args.flag("read")?;
+                let read_write = args.flag("read_write")?;
+                args.finish(&[&["read", "read_write"], ENTRY_OPTIONS].concat())?;
+                if read == read_write {
+                    return Err(syn::Error::new(
+                        args_span(attr),
+                        "#[storage] needs exactly one access mode: `read` or `read_write`",
+                    ));
+                }
+                let variant = EntryVariant::Storage { read_only: read };
+                self.push_entry(attr, field_ident, field_type, options, variant)?;
+            }
+            "sampler" => {
+                let options = Self::entry_options(&mut args)?;
+                let filter = args.choice("filter", &["nearest", "linear"])?;
+                let address = args.choice(
+                    "address",
+                    &["clamp_to_edge", "repeat", "mirror_repeat"],
+                )?;
+                let compare = args.choice("compare", COMPARE_FUNCTIONS)?;
+                args.finish(&[&["filter", "address", "compare"], ENTRY_OPTIONS].concat())?;
+                let variant = EntryVariant::Sampler {
+                    filter: filter.map_or_else(|| format_ident!("Nearest"), wgpu_variant),
+                    address: address.map_or_else(|| format_ident!("ClampToEdge"), wgpu_variant),
+                    compare: compare.map(wgpu_variant),
+                };
+                self.push_entry(attr, field_ident, field_type, options, variant)?;
+            }
+            "group" if self.compute => {
+                let index = args.int("index")?;
+                args.finish(&["index"])?;
+                let visibility = Visibility {
+                    vertex: false,
+                    fragment: false,
+                    compute: true,
+                    span: attr.path.span(),
+                };
+                self.push_group(attr, index, visibility)?;
+            }
+            "push_constants" => {
+                let stages = if self.compute {
+                    match args.expr("stages")? {
+                        Some(expr) => flag_names(&expr, "stages", &["compute"])?,
+                        None => vec![format_ident!("COMPUTE")],
+                    }
+                } else {
+                    flag_names(
+                        &args.required_expr("stages")?,
+                        "stages",
+                        &["vertex", "fragment"],
+                    )?
+                };
+                args.finish(&["stages"])?;
+                if let Some(push_constants) = &self.push_constants {
+                    let mut error = syn::Error::new(
+                        field_ident.span(),
+                        "a shader takes a single #[push_constants] field",
+                    );
+                    error.combine(syn::Error::new(
+                        push_constants.span,
+                        "first declared here",
+                    ));
+                    return Err(error);
+                }
+                self.push_constants = Some(PushConstants {
+                    ty: field_type.clone(),
+                    stages,
+                    span: field_ident.span(),
                 });
             }
+            "vertex" | "instance" | "index" | "output" if self.compute => {
+                return Err(syn::Error::new(
+                    attr.path.span(),
+                    format!("`#[{}]` is only supported by render pipelines", ident),
+                ))
+            }
             "group" => {
-                self.bind_groups.push(Group {
-                    ident: format!("group_{}", self.bind_groups.len()),
-                    vertex: inner.contains("vertex"),
-                    fragment: inner.contains("fragment"),
-                    entries: vec![],
+                let vertex = args.flag("vertex")?;
+                let fragment = args.flag("fragment")?;
+                let index = args.int("index")?;
+                args.finish(&["vertex", "fragment", "index"])?;
+                let visibility = Visibility {
+                    vertex,
+                    fragment,
+                    compute: false,
+                    span: args_span(attr),
+                };
+                self.push_group(attr, index, visibility)?;
+            }
+            "vertex" | "instance" => {
+                args.finish(&[])?;
+                self.vertex_buffers.push(Vertex {
+                    ty: field_type.clone(),
+                    instance: ident == "instance",
+                    span: field_ident.span(),
+                });
+            }
+            "index" => {
+                let u16 = args.flag("u16")?;
+                let u32 = args.flag("u32")?;
+                args.finish(&["u16", "u32"])?;
+                if u16 == u32 {
+                    return Err(syn::Error::new(
+                        args_span(attr),
+                        "#[index] needs exactly one index type: `u16` or `u32`",
+                    ));
+                }
+                if let Some(index) = self.index_buffers.first() {
+                    let mut error = syn::Error::new(
+                        field_ident.span(),
+                        "a shader takes a single #[index] buffer",
+                    );
+                    error.combine(syn::Error::new(index.span, "first declared here"));
+                    return Err(error);
+                }
+                self.index_buffers.push(Index {
+                    element: format_ident!("{}", if u16 { "u16" } else { "u32" }),
+                    span: field_ident.span(),
+                });
+            }
+            "output" => {
+                let format = args.ident("format")?.map(Format::parse).transpose()?;
+                let blend = args.choice("blend", &["alpha", "additive", "replace"])?;
+                let write_mask = match args.expr("write_mask")? {
+                    Some(expr) => flag_names(
+                        &expr,
+                        "write_mask",
+                        &["red", "green", "blue", "alpha", "color", "all"],
+                    )?,
+                    None => vec![format_ident!("ALL")],
+                };
+                let clear = match args.expr("load")? {
+                    Some(expr) => load_op(&expr)?,
+                    None => None,
+                };
+                let store = args.choice("store", &["store", "discard"])?;
+                args.finish(&["format", "blend", "write_mask", "load", "store"])?;
+                if let Some(format) = &format {
+                    if !format.renderable() {
+                        return Err(syn::Error::new(
+                            format.ident.span(),
+                            format!("`{}` can't be rendered to as an #[output]", format.ident),
+                        ));
+                    }
+                    if blend.is_some() && !format.blendable() {
+                        return Err(syn::Error::new(
+                            format.ident.span(),
+                            format!("`{}` can't be blended, remove `blend`", format.ident),
+                        ));
+                    }
+                }
+                self.outputs.push(Output {
+                    ident: field_ident.to_string(),
+                    format,
+                    blend: blend.map(wgpu_variant),
+                    write_mask,
+                    clear,
+                    store: store.is_none_or(|store| store == "store"),
+                    span: field_ident.span(),
                 });
             }
-            "vertex" => self.vertex_buffers.push(Vertex {}),
-            "output" => self.outputs.push(Output {}),
-            _ => panic!("Unknown attribute: {}", ident),
+            _ => {
+                return Err(syn::Error::new(
+                    attr.path.span(),
+                    format!(
+                        "unknown #[shader] field attribute `#[{}]`, expected one of: group, texture2d, texture2d_array, texture3d, texture_cube, storage_texture, sampler, uniform, storage, push_constants, vertex, instance, index, output",
+                        ident
+                    ),
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    /// `index = N` defaults to one past the previous group's index.
+    fn push_group(
+        &mut self,
+        attr: &Attribute,
+        index: Option<(Span, u32)>,
+        visibility: Visibility,
+    ) -> syn::Result<()> {
+        let (span, index) = match index {
+            Some(index) => index,
+            None => (
+                attr.path.span(),
+                self.bind_groups.last().map_or(0, |group| group.index + 1),
+            ),
+        };
+        if let Some(other) = self.bind_groups.iter().find(|group| group.index == index) {
+            let mut error = syn::Error::new(span, format!("duplicate group index {}", index));
+            error.combine(syn::Error::new(other.span, "first declared here"));
+            return Err(error);
+        }
+        self.bind_groups.push(Group {
+            index,
+            visibility,
+            entries: vec![],
+            span: attr.path.span(),
+        });
+        Ok(())
+    }
+
+    /// Any stage is accepted, so a layout can also be shared with pipelines of the other kind.
+    fn entry_options(args: &mut Args) -> syn::Result<EntryOptions> {
+        let visibility = match args.expr("visibility")? {
+            Some(expr) => {
+                let stages = flag_names(&expr, "visibility", &["vertex", "fragment", "compute"])?;
+                let visible = |stage: &str| stages.iter().any(|flag| flag == stage);
+                Some(Visibility {
+                    vertex: visible("VERTEX"),
+                    fragment: visible("FRAGMENT"),
+                    compute: visible("COMPUTE"),
+                    span: expr.span(),
+                })
+            }
+            None => None,
+        };
+        Ok(EntryOptions {
+            binding: args.int("binding")?,
+            visibility,
+        })
+    }
+
+    /// `binding = N` defaults to one past the previous entry's binding in the group.
+    fn push_entry(
+        &mut self,
+        attr: &Attribute,
+        field_ident: &Ident,
+        field_type: &syn::Type,
+        options: EntryOptions,
+        variant: EntryVariant,
+    ) -> syn::Result<()> {
+        let Some(group) = self.bind_groups.last_mut() else {
+            return Err(syn::Error::new(
+                attr.path.span(),
+                format!(
+                    "`#[{}]` on `{}` must come after a `#[group(...)]` attribute, on this field or an earlier one",
+                    attr.path.get_ident().unwrap(),
+                    field_ident
+                ),
+            ));
+        };
+        let (span, binding) = match options.binding {
+            Some(binding) => binding,
+            None => (
+                field_ident.span(),
+                group.entries.last().map_or(0, |entry| entry.binding + 1),
+            ),
+        };
+        if let Some(other) = group.entries.iter().find(|entry| entry.binding == binding) {
+            let mut error = syn::Error::new(
+                span,
+                format!(
+                    "duplicate binding {} in group {}, already used by `{}`",
+                    binding, group.index, other.ident
+                ),
+            );
+            error.combine(syn::Error::new(other.span, "first declared here"));
+            return Err(error);
+        }
+        let visibility = options.visibility.unwrap_or(group.visibility);
+        if !visibility.vertex && !visibility.fragment && !visibility.compute {
+            return Err(syn::Error::new(
+                field_ident.span(),
+                format!(
+                    "`{}` has no visibility, add `visibility = vertex | fragment` to it or `vertex`/`fragment` to its #[group]",
+                    field_ident
+                ),
+            ));
         }
+        group.entries.push(Entry {
+            ident: field_ident.to_string(),
+            ty: field_type.clone(),
+            binding,
+            visibility,
+            variant,
+            span: field_ident.span(),
+        });
+        Ok(())
     }
 }
 
-fn inner_args(inner: &str) -> HashMap<&str, &str> {
-    let inner = inner.get(1..inner.len() - 1).unwrap();
-    let args = crate::parser::comma_tuple(inner).unwrap().1;
-    let args = args
-        .into_iter()
-        .map(|args| key_val(args).unwrap().1)
-        .collect::<HashMap<_, _>>();
-    args
+/// `#[texture2d(width, height)]`, `#[texture2d_array(width, height, layers)]`,
+/// `#[texture3d(width, height, depth)]` or `#[texture_cube(size)]`, each taking an optional
+/// `format` and `filterable`.
+fn texture_variant(attr_name: &str, mut args: Args) -> syn::Result<EntryVariant> {
+    let (kind, width, height, depth_or_array_layers, sizes): (_, Expr, Expr, Expr, &[&str]) =
+        match attr_name {
+            "texture2d" => (
+                TextureKind::D2,
+                args.required_expr("width")?,
+                args.required_expr("height")?,
+                parse_quote!(1),
+                &["width", "height"],
+            ),
+            "texture2d_array" => (
+                TextureKind::D2Array,
+                args.required_expr("width")?,
+                args.required_expr("height")?,
+                args.required_expr("layers")?,
+                &["width", "height", "layers"],
+            ),
+            "texture3d" => (
+                TextureKind::D3,
+                args.required_expr("width")?,
+                args.required_expr("height")?,
+                args.required_expr("depth")?,
+                &["width", "height", "depth"],
+            ),
+            _ => {
+                let size = args.required_expr("size")?;
+                (
+                    TextureKind::Cube,
+                    size.clone(),
+                    size,
+                    parse_quote!(6),
+                    &["size"],
+                )
+            }
+        };
+    let filterable = args.flag("filterable")?;
+    let format = args.ident("format")?.map(Format::parse).transpose()?;
+    args.finish(&[sizes, &["format", "filterable"], ENTRY_OPTIONS].concat())?;
+    if let Some(format) = &format {
+        check_texture_format(attr_name, format, filterable)?;
+    }
+    Ok(EntryVariant::Texture {
+        kind,
+        width: Box::new(width),
+        height: Box::new(height),
+        depth_or_array_layers: Box::new(depth_or_array_layers),
+        filterable,
+        format,
+    })
+}
+
+/// Texture entries need a format shaders can read and whose texels have a fixed size.
+fn check_texture_format(attr_name: &str, format: &Format, filterable: bool) -> syn::Result<()> {
+    let Some(sample_type) = format
+        .sample_type()
+        .filter(|_| format.block_size().is_some())
+    else {
+        return Err(syn::Error::new(
+            format.ident.span(),
+            format!(
+                "`{}` has no single sample type and texel size, it can't back a #[{}]",
+                format.ident, attr_name
+            ),
+        ));
+    };
+    if filterable && sample_type != (TextureSampleType::Float { filterable: true }) {
+        return Err(syn::Error::new(
+            format.ident.span(),
+            format!("`{}` can't be filtered, remove `filterable`", format.ident),
+        ));
+    }
+    Ok(())
+}
+
+/// `load = load` or `load = clear(r, g, b, a)`, returning the clear color of the latter.
+fn load_op(expr: &Expr) -> syn::Result<Option<Vec<Expr>>> {
+    match expr {
+        Expr::Path(path) if path.path.is_ident("load") => return Ok(None),
+        Expr::Call(ExprCall { func, args, .. }) if args.len() == 4 => {
+            if let Expr::Path(path) = &**func {
+                if path.path.is_ident("clear") {
+                    return Ok(Some(args.iter().cloned().collect()));
+                }
+            }
+        }
+        _ => {}
+    }
+    Err(syn::Error::new(
+        expr.span(),
+        "expected `load = load` or `load = clear(r, g, b, a)`",
+    ))
+}
+
+/// `key = a | b | ...` out of `choices`, as upper case `wgpu` bitflag constant names.
+fn flag_names(expr: &Expr, key: &str, choices: &[&str]) -> syn::Result<Vec<Ident>> {
+    match expr {
+        Expr::Binary(ExprBinary {
+            left,
+            op: BinOp::BitOr(_),
+            right,
+            ..
+        }) => {
+            let mut flags = flag_names(left, key, choices)?;
+            flags.extend(flag_names(right, key, choices)?);
+            Ok(flags)
+        }
+        Expr::Path(path) => match path.path.get_ident() {
+            Some(ident) if choices.iter().any(|choice| ident == choice) => Ok(vec![Ident::new(
+                &ident.to_string().to_uppercase(),
+                ident.span(),
+            )]),
+            _ => Err(flag_names_error(expr, key, choices)),
+        },
+        _ => Err(flag_names_error(expr, key, choices)),
+    }
 }
 
-pub fn comma_tuple(input: &str) -> IResult<&str, Vec<&str>> {
-    separated_list0(tag(", "), take_till(|c| c == ',' || c == ')'))(input)
+fn flag_names_error(expr: &Expr, key: &str, choices: &[&str]) -> syn::Error {
+    syn::Error::new(
+        expr.span(),
+        format!(
+            "expected `{}` to combine {} with `|`",
+            key,
+            choices.join(", ")
+        ),
+    )
 }
 
-pub fn key_val(input: &str) -> IResult<&str, (&str, &str)> {
-    separated_pair(take_until(" "), tag(" = "), alphanumeric0)(input)
+/// Maps a snake case option like `clamp_to_edge` to its `wgpu` variant, `ClampToEdge`.
+fn wgpu_variant(option: Ident) -> Ident {
+    Ident::new(&camel_case(&option.to_string()), option.span())
 }
 
-// // Returns a parser which applies the given parser and then consumes 1 byte off the remainder
-// fn take1<'a, O, F>(mut parser: F) -> impl FnMut(&'a str) -> IResult<&'a str, O>
-// where
-//     F: FnMut(&'a str) -> IResult<&'a str, O>,
-// {
-//     move |input: &'a str| {
-//         let (input, output) = parser(input)?;
-//         Ok((take(1usize)(input)?.1, output))
-//     }
-// }
+/// The span of an attribute's argument tokens, or of its name if it has none.
+fn args_span(attr: &Attribute) -> Span {
+    if attr.tokens.is_empty() {
+        attr.path.span()
+    } else {
+        attr.tokens.span()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn depth(attrs: &str) -> syn::Result<Option<Depth>> {
+        let input =
+            syn::parse_str::<syn::DeriveInput>(&format!("{} struct S {{}}", attrs)).unwrap();
+        depth_attr(&input.attrs)
+    }
+
+    #[test]
+    fn parses_depth_with_wgpu_variant_names() {
+        let depth = depth("#[depth(format = Depth32Float, compare = Less, write = true)]")
+            .unwrap()
+            .unwrap();
+        assert_eq!(depth.format.ident, "Depth32Float");
+        assert_eq!(depth.compare, "Less");
+        assert!(depth.write);
+        assert!(depth.stencil.is_none());
+    }
+
+    #[test]
+    fn parses_snake_case_aliases() {
+        let depth = depth("#[depth(compare = less_equal, write = false)]")
+            .unwrap()
+            .unwrap();
+        assert_eq!(depth.format.ident, "Depth32Float");
+        assert_eq!(depth.compare, "LessEqual");
+        assert!(!depth.write);
+    }
+
+    #[test]
+    fn parses_stencil_operations() {
+        let depth = depth(
+            "#[depth(format = Depth24PlusStencil8, stencil(compare = Equal, pass = IncrementClamp, fail = zero))]",
+        )
+        .unwrap()
+        .unwrap();
+        let stencil = depth.stencil.unwrap();
+        assert_eq!(stencil.compare, "Equal");
+        assert_eq!(stencil.pass, "IncrementClamp");
+        assert_eq!(stencil.fail, "Zero");
+        assert_eq!(stencil.depth_fail, "Keep");
+    }
+
+    #[test]
+    fn rejects_unknown_compare_functions() {
+        let error = depth("#[depth(compare = Lesser)]").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "expected `compare` to be one of: Never, Less, Equal, LessEqual, Greater, NotEqual, GreaterEqual, Always"
+        );
+    }
+}
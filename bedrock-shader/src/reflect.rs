@@ -0,0 +1,167 @@
+use naga::{proc::Layouter, AddressSpace, ImageClass, ImageDimension, ShaderStage, TypeInner};
+use quote::format_ident;
+use syn::{parse_quote, Ident};
+
+use crate::{
+    camel_case,
+    parser::ReflectArgs,
+    schema::{Entry, EntryVariant, Group, Output, Pipeline, Shader, TextureKind, Visibility},
+    source::{describe_global, global_name, ShaderModule, ShaderSource},
+};
+
+impl Shader {
+    /// Builds the whole interface from the WGSL module: one entry per bound global (typed as a
+    /// struct generated inside `shader_mod_ident`) at its WGSL group and binding, visible to the
+    /// entry points that use it, and one output per `@location` written by the fragment entry
+    /// point of `pipeline`.
+    pub fn reflect(
+        source: ShaderSource,
+        shader_module: &ShaderModule,
+        pipeline: Pipeline,
+        args: &ReflectArgs,
+        shader_ident: &Ident,
+        shader_mod_ident: &Ident,
+    ) -> syn::Result<Self> {
+        let module = &shader_module.module;
+        let mut layouter = Layouter::default();
+        layouter
+            .update(module.to_ctx())
+            .map_err(|err| syn::Error::new(source.span, err.to_string()))?;
+
+        let mut globals = module
+            .global_variables
+            .iter()
+            .filter_map(|(handle, global)| Some((global.binding.clone()?, handle, global)))
+            .collect::<Vec<_>>();
+        globals.sort_by_key(|(resource, ..)| (resource.group, resource.binding));
+
+        let mut bind_groups = Vec::<Group>::new();
+        let mut generated_types = vec![];
+        for (resource, handle, global) in globals {
+            let mut visibility = Visibility {
+                vertex: false,
+                fragment: false,
+                compute: false,
+                span: args.span,
+            };
+            if bind_groups.last().map(|group| group.index) != Some(resource.group) {
+                bind_groups.push(Group {
+                    index: resource.group,
+                    visibility,
+                    entries: vec![],
+                    span: args.span,
+                });
+            }
+            let group = bind_groups.last_mut().unwrap();
+
+            let variant = match (&global.space, &module.types[global.ty].inner) {
+                (
+                    AddressSpace::Handle,
+                    TypeInner::Image {
+                        dim: ImageDimension::D2,
+                        arrayed: false,
+                        class: ImageClass::Sampled { .. },
+                    },
+                ) => {
+                    let (Some(width), Some(height)) = (&args.width, &args.height) else {
+                        return Err(syn::Error::new(
+                            args.span,
+                            format!(
+                                "reflected texture `{}` needs a size, use `reflect(width = .., height = ..)`",
+                                global_name(global)
+                            ),
+                        ));
+                    };
+                    EntryVariant::Texture {
+                        kind: TextureKind::D2,
+                        width: Box::new(width.clone()),
+                        height: Box::new(height.clone()),
+                        depth_or_array_layers: Box::new(parse_quote!(1)),
+                        filterable: false,
+                        format: None,
+                    }
+                }
+                (AddressSpace::Uniform, _) => {
+                    let size = layouter[global.ty].size as u64;
+                    EntryVariant::Uniform {
+                        size: Some(parse_quote!(#size)),
+                        dynamic: false,
+                    }
+                }
+                _ => {
+                    return Err(syn::Error::new(
+                        source.span,
+                        format!(
+                        "@group({}) @binding({}) `{}` is a {}, which reflection does not support",
+                        resource.group,
+                        resource.binding,
+                        global_name(global),
+                        describe_global(module, global)
+                    ),
+                    ))
+                }
+            };
+
+            for (stage, entry_point) in shader_module.pipeline_entry_points(&pipeline) {
+                if !shader_module.info.get_entry_point(entry_point)[handle].is_empty() {
+                    match stage {
+                        ShaderStage::Vertex => visibility.vertex = true,
+                        ShaderStage::Fragment => visibility.fragment = true,
+                        ShaderStage::Compute => visibility.compute = true,
+                    }
+                }
+            }
+            // Bindings no entry point touches still need a valid visibility.
+            if !visibility.vertex && !visibility.fragment {
+                visibility.vertex = true;
+                visibility.fragment = true;
+            }
+
+            let name = global_name(global);
+            let type_ident = format_ident!("{}", camel_case(name));
+            group.entries.push(Entry {
+                ident: name.to_string(),
+                ty: parse_quote!(#shader_mod_ident::#type_ident),
+                binding: resource.binding,
+                visibility,
+                variant,
+                span: args.span,
+            });
+            generated_types.push(type_ident);
+        }
+
+        let fragment_outputs = match &pipeline {
+            Pipeline::Render {
+                fragment: Some(fragment),
+                ..
+            } => shader_module.fragment_outputs(fragment),
+            _ => None,
+        };
+        let outputs = (0..fragment_outputs.unwrap_or(0))
+            .map(|index| Output {
+                ident: format!("output_{}", index),
+                format: None,
+                blend: None,
+                write_mask: vec![format_ident!("ALL")],
+                clear: None,
+                store: true,
+                span: args.span,
+            })
+            .collect();
+
+        Ok(Shader {
+            source,
+            pipeline,
+            vertex_buffers: vec![],
+            index_buffers: vec![],
+            bind_groups,
+            outputs,
+            depth: None,
+            push_constants: None,
+            samples: 1,
+            primitive: Default::default(),
+            generated_types,
+            span: shader_ident.span(),
+        })
+    }
+}
@@ -1,38 +1,197 @@
+use proc_macro2::Span;
+
+use crate::{format::Format, source::ShaderSource};
+
+/// Entry points used unless `#[shader]` or `#[compute_shader]` names others.
+pub const VERTEX_ENTRY_POINT: &str = "vs_main";
+pub const FRAGMENT_ENTRY_POINT: &str = "fs_main";
+pub const COMPUTE_ENTRY_POINT: &str = "cs_main";
+
+/// A bind group, kept sorted by `index`. Indices may skip numbers, the generated pipeline
+/// layout fills the gaps with empty groups.
 #[derive(Debug)]
 pub struct Group {
-    pub ident: String,
-    pub vertex: bool,
-    pub fragment: bool,
+    /// The WGSL `@group` number.
+    pub index: u32,
+    /// The default visibility of the group's entries.
+    pub visibility: Visibility,
+    /// Sorted by `binding`.
     pub entries: Vec<Entry>,
+    pub span: Span,
 }
 
 #[derive(Debug)]
 pub struct Entry {
     pub ident: String,
-    pub ty: String,
+    pub ty: syn::Type,
+    /// The WGSL `@binding` number.
+    pub binding: u32,
+    /// The entry's own `visibility`, or its group's.
+    pub visibility: Visibility,
     pub variant: EntryVariant,
+    pub span: Span,
+}
+
+/// The shader stages a binding is visible to, and where that was declared.
+#[derive(Debug, Clone, Copy)]
+pub struct Visibility {
+    pub vertex: bool,
+    pub fragment: bool,
+    pub compute: bool,
+    pub span: Span,
 }
 
 #[derive(Debug)]
 pub enum EntryVariant {
-    Texture2D { width: u32, height: u32 },
-    Uniform { size: u64 },
+    Texture {
+        kind: TextureKind,
+        width: Box<syn::Expr>,
+        height: Box<syn::Expr>,
+        /// The depth of 3D textures, the layer count of arrays, 6 for cubes and 1 otherwise.
+        depth_or_array_layers: Box<syn::Expr>,
+        filterable: bool,
+        /// `None` uses the swapchain format, known only at runtime.
+        format: Option<Format>,
+    },
+    /// `size` is set for raw uniforms; without it the field type is a `ShaderType` whose
+    /// WGSL size is computed by encase. `dynamic` uniforms are bound from a `UniformRing` at an
+    /// offset given per draw.
+    Uniform {
+        size: Option<syn::Expr>,
+        dynamic: bool,
+    },
+    /// A runtime-sized array of the field type, a `ShaderType`.
+    Storage { read_only: bool },
+    /// A 2D texture shaders load from or store to directly, `access` holds a
+    /// `wgpu::StorageTextureAccess` variant name.
+    StorageTexture {
+        width: Box<syn::Expr>,
+        height: Box<syn::Expr>,
+        format: Format,
+        access: syn::Ident,
+    },
+    /// Filter, address and compare modes hold `wgpu` variant names, e.g. `Linear`.
+    Sampler {
+        filter: syn::Ident,
+        address: syn::Ident,
+        compare: Option<syn::Ident>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureKind {
+    D2,
+    D2Array,
+    D3,
+    Cube,
+}
+
+/// The pipeline kind and the names of its entry points.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pipeline {
+    /// `fragment` is `None` for vertex-only pipelines.
+    Render {
+        vertex: String,
+        fragment: Option<String>,
+    },
+    Compute {
+        entry_point: String,
+        workgroup_size: [u32; 3],
+    },
 }
 
 #[derive(Debug)]
 pub struct Shader {
-    pub path: String,
+    pub source: ShaderSource,
+    pub pipeline: Pipeline,
     pub vertex_buffers: Vec<Vertex>,
     pub index_buffers: Vec<Index>,
     pub bind_groups: Vec<Group>,
     pub outputs: Vec<Output>,
+    pub depth: Option<Depth>,
+    pub push_constants: Option<PushConstants>,
+    /// MSAA sample count of every attachment, 1 without multisampling.
+    pub samples: u32,
+    pub primitive: wgpu::PrimitiveState,
+    /// Entry types the macro defines itself inside the shader module (reflection mode).
+    pub generated_types: Vec<syn::Ident>,
+    pub span: Span,
 }
 
+/// An `#[index(u16 | u32)]` buffer, `element` names the index type.
 #[derive(Debug)]
-pub struct Index {}
+pub struct Index {
+    pub element: syn::Ident,
+    pub span: Span,
+}
 
+impl Index {
+    pub fn format(&self) -> wgpu::IndexFormat {
+        if self.element == "u16" {
+            wgpu::IndexFormat::Uint16
+        } else {
+            wgpu::IndexFormat::Uint32
+        }
+    }
+}
+
+/// A `#[vertex]` or `#[instance]` buffer of the field type, a `VertexProvider`.
+#[derive(Debug)]
+pub struct Vertex {
+    pub ty: syn::Type,
+    /// Stepped per instance rather than per vertex.
+    pub instance: bool,
+    pub span: Span,
+}
+
+/// The struct-level `#[depth(...)]` attachment. Comparisons and operations hold `wgpu`
+/// variant names, e.g. `LessEqual`.
+#[derive(Debug)]
+pub struct Depth {
+    pub format: Format,
+    pub compare: syn::Ident,
+    pub write: bool,
+    /// The depth to clear to, `None` loads the previous contents.
+    pub clear: Option<syn::Expr>,
+    pub stencil: Option<Stencil>,
+}
+
+/// `stencil(...)` options of `#[depth]`, applied to both faces.
 #[derive(Debug)]
-pub struct Vertex {}
+pub struct Stencil {
+    pub compare: syn::Ident,
+    pub fail: syn::Ident,
+    pub depth_fail: syn::Ident,
+    pub pass: syn::Ident,
+    pub read_mask: syn::Expr,
+    pub write_mask: syn::Expr,
+    pub reference: syn::Expr,
+    /// The stencil value to clear to, `None` loads the previous contents.
+    pub clear: Option<syn::Expr>,
+}
 
+/// A `#[push_constants]` field of a `bytemuck::Pod` type.
 #[derive(Debug)]
-pub struct Output {}
+pub struct PushConstants {
+    pub ty: syn::Type,
+    /// `wgpu::ShaderStages` constant names.
+    pub stages: Vec<syn::Ident>,
+    pub span: Span,
+}
+
+/// An `#[output]` color target.
+#[derive(Debug)]
+pub struct Output {
+    pub ident: String,
+    /// `None` uses the swapchain format, known only at runtime.
+    pub format: Option<Format>,
+    /// `Alpha`, `Additive` or `Replace`, `None` writes without blending.
+    pub blend: Option<syn::Ident>,
+    /// `wgpu::ColorWrites` constant names.
+    pub write_mask: Vec<syn::Ident>,
+    /// The `r, g, b, a` color to clear to, `None` loads the previous contents.
+    pub clear: Option<Vec<syn::Expr>>,
+    /// `false` discards the rendered contents after the pass.
+    pub store: bool,
+    pub span: Span,
+}
@@ -0,0 +1,592 @@
+use std::path::{Path, PathBuf};
+
+use bedrock_preprocess::{is_identifier, preprocess};
+use codespan_reporting::{
+    diagnostic::{Diagnostic, Label},
+    files::SimpleFiles,
+    term::{self, termcolor::NoColor},
+};
+use naga::{
+    valid::{Capabilities, ModuleInfo, ValidationFlags, Validator},
+    AddressSpace, Binding, GlobalVariable, Handle, ShaderStage, TypeInner,
+};
+use proc_macro2::Span;
+use syn::LitStr;
+
+use crate::schema::Pipeline;
+
+/// A WGSL file referenced by a `#[shader("...")]` attribute, resolved on disk.
+#[derive(Debug)]
+pub struct ShaderSource {
+    pub path: PathBuf,
+    /// The file with its preprocessor directives expanded, followed by every module it imports.
+    /// Modules are appended rather than inlined so the file keeps its line numbers, WGSL
+    /// declarations being order independent.
+    pub source: String,
+    /// The file itself and then its imported modules, in the order they appear in `source`.
+    pub files: Vec<SourceFile>,
+    pub span: Span,
+}
+
+/// One file of a `ShaderSource`, expanded with its `#import` lines left empty.
+#[derive(Debug)]
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub source: String,
+}
+
+/// A parsed and validated WGSL module.
+pub struct ShaderModule {
+    pub module: naga::Module,
+    pub info: ModuleInfo,
+}
+
+impl ShaderSource {
+    /// Resolves `path` relative to the file containing the attribute, falling back to the
+    /// invoking crate's manifest directory, reads it along with the modules it imports and
+    /// expands their directives with `defines`.
+    pub fn load(path: &LitStr, defines: &[(String, String)]) -> syn::Result<Self> {
+        let relative = PathBuf::from(path.value());
+        let candidates = candidate_paths(path, &relative);
+
+        let resolved = candidates
+            .iter()
+            .find(|candidate| candidate.is_file())
+            .ok_or_else(|| {
+                let tried = candidates
+                    .iter()
+                    .map(|candidate| format!("\n  {}", candidate.display()))
+                    .collect::<String>();
+                syn::Error::new(
+                    path.span(),
+                    format!("shader file `{}` not found, tried:{}", path.value(), tried),
+                )
+            })?;
+
+        let resolved = resolved.canonicalize().unwrap_or_else(|_| resolved.clone());
+        let defines = defines
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect::<Vec<_>>();
+        let mut loader = ImportLoader {
+            span: path.span(),
+            defines: &defines,
+            root_dir: resolved.parent().map(Path::to_path_buf).unwrap_or_default(),
+            files: vec![],
+            stack: vec![],
+        };
+        loader.load(resolved.clone())?;
+        let files = loader.files;
+
+        Ok(Self {
+            path: resolved,
+            source: files.iter().map(|file| file.source.as_str()).collect(),
+            files,
+            span: path.span(),
+        })
+    }
+
+    /// Parses and validates the WGSL with naga, reporting failures at the attribute's path.
+    pub fn module(&self) -> syn::Result<ShaderModule> {
+        let path = self.path.to_string_lossy();
+        let module = naga::front::wgsl::parse_str(&self.source).map_err(|err| {
+            self.naga_error(
+                err.message().to_string(),
+                err.labels()
+                    .map(|(span, label)| (span, label.to_string()))
+                    .collect(),
+                vec![],
+                || err.emit_to_string_with_path(&self.source, &path),
+            )
+        })?;
+        let info = Validator::new(ValidationFlags::all(), Capabilities::all())
+            .validate(&module)
+            .map_err(|err| {
+                let mut notes = vec![];
+                let mut source: &dyn std::error::Error = err.as_inner();
+                while let Some(next) = source.source() {
+                    notes.push(next.to_string());
+                    source = next;
+                }
+                self.naga_error(
+                    err.as_inner().to_string(),
+                    err.spans().cloned().collect(),
+                    notes,
+                    || err.emit_to_string_with_path(&self.source, &path),
+                )
+            })?;
+
+        Ok(ShaderModule { module, info })
+    }
+
+    /// Reports a naga error at the attribute. Errors within the file itself keep naga's own
+    /// rendering, ones reaching into imported modules are rendered against those modules so the
+    /// reported paths and lines are the original ones.
+    fn naga_error(
+        &self,
+        message: String,
+        labels: Vec<(naga::Span, String)>,
+        notes: Vec<String>,
+        emit: impl FnOnce() -> String,
+    ) -> syn::Error {
+        let root_len = self.files[0].source.len();
+        if labels
+            .iter()
+            .all(|(span, _)| span.to_range().is_none_or(|range| range.end <= root_len))
+        {
+            return syn::Error::new(self.span, emit());
+        }
+
+        let mut files = SimpleFiles::new();
+        let mut offset = 0;
+        let ranges = self
+            .files
+            .iter()
+            .map(|file| {
+                let id = files.add(file.path.display().to_string(), file.source.as_str());
+                let range = offset..offset + file.source.len();
+                offset = range.end;
+                (id, range)
+            })
+            .collect::<Vec<_>>();
+        let labels = labels
+            .into_iter()
+            .filter_map(|(span, label)| {
+                let range = span.to_range()?;
+                let (id, file) = ranges
+                    .iter()
+                    .find(|(_, file)| file.contains(&range.start))?;
+                let end = range.end.min(file.end);
+                Some(
+                    Label::primary(*id, range.start - file.start..end - file.start)
+                        .with_message(label),
+                )
+            })
+            .collect();
+        let diagnostic = Diagnostic::error()
+            .with_message(message)
+            .with_labels(labels)
+            .with_notes(notes);
+        let mut writer = NoColor::new(Vec::new());
+        term::emit(&mut writer, &term::Config::default(), &files, &diagnostic)
+            .expect("cannot write error");
+        syn::Error::new(
+            self.span,
+            String::from_utf8_lossy(&writer.into_inner()).into_owned(),
+        )
+    }
+}
+
+/// Reads a file and, depth first, the modules it imports, loading each file once.
+struct ImportLoader<'a> {
+    span: Span,
+    defines: &'a [(&'a str, &'a str)],
+    /// Directory of the `#[shader]` file, where `module::name` imports are looked up.
+    root_dir: PathBuf,
+    files: Vec<SourceFile>,
+    /// Files whose imports are being loaded, to report cycles.
+    stack: Vec<PathBuf>,
+}
+
+impl ImportLoader<'_> {
+    fn load(&mut self, path: PathBuf) -> syn::Result<()> {
+        let source = std::fs::read_to_string(&path).map_err(|err| {
+            syn::Error::new(
+                self.span,
+                format!("failed to read `{}`: {}", path.display(), err),
+            )
+        })?;
+        let source = preprocess(&source, self.defines).map_err(|err| {
+            syn::Error::new(
+                self.span,
+                format!("failed to preprocess `{}`, {}", path.display(), err),
+            )
+        })?;
+
+        let mut imports = vec![];
+        let mut expanded = String::with_capacity(source.len());
+        for (index, line) in source.lines().enumerate() {
+            match line.trim_start().strip_prefix("#import") {
+                Some(target) => imports.push((index + 1, target.trim())),
+                None => expanded.push_str(line),
+            }
+            expanded.push('\n');
+        }
+        self.files.push(SourceFile {
+            path: path.clone(),
+            source: expanded,
+        });
+
+        self.stack.push(path.clone());
+        for (line, target) in imports {
+            let location = format!("{}:{}", path.display(), line);
+            let import = self.resolve(&path, &location, target)?;
+            if let Some(start) = self.stack.iter().position(|file| *file == import) {
+                let cycle = self.stack[start..]
+                    .iter()
+                    .chain([&import])
+                    .map(|file| file_name(file))
+                    .collect::<Vec<_>>();
+                return Err(syn::Error::new(
+                    self.span,
+                    format!("{}: import cycle {}", location, cycle.join(" -> ")),
+                ));
+            }
+            if self.files.iter().all(|file| file.path != import) {
+                self.load(import)?;
+            }
+        }
+        self.stack.pop();
+        Ok(())
+    }
+
+    /// `"path.wgsl"` relative to the importing file, or `module::name` for `module/name.wgsl`
+    /// relative to the `#[shader]` file, both falling back to the crate's manifest directory.
+    fn resolve(&self, from: &Path, location: &str, target: &str) -> syn::Result<PathBuf> {
+        let (relative, dir) = if let Some(quoted) = target
+            .strip_prefix('"')
+            .and_then(|target| target.strip_suffix('"'))
+        {
+            (PathBuf::from(quoted), from.parent())
+        } else if !target.is_empty() && target.split("::").all(is_identifier) {
+            let mut relative = target.split("::").collect::<PathBuf>();
+            relative.set_extension("wgsl");
+            (relative, Some(self.root_dir.as_path()))
+        } else {
+            return Err(syn::Error::new(
+                self.span,
+                format!(
+                    "{}: expected `#import \"path.wgsl\"` or `#import module::name`",
+                    location
+                ),
+            ));
+        };
+
+        let mut candidates = vec![];
+        if relative.is_absolute() {
+            candidates.push(relative);
+        } else {
+            candidates.extend(dir.map(|dir| dir.join(&relative)));
+            if let Ok(manifest_dir) = std::env::var("CARGO_MANIFEST_DIR") {
+                candidates.push(Path::new(&manifest_dir).join(&relative));
+            }
+        }
+        let Some(found) = candidates.iter().find(|candidate| candidate.is_file()) else {
+            let tried = candidates
+                .iter()
+                .map(|candidate| format!("\n  {}", candidate.display()))
+                .collect::<String>();
+            return Err(syn::Error::new(
+                self.span,
+                format!(
+                    "{}: imported module `{}` not found, tried:{}",
+                    location, target, tried
+                ),
+            ));
+        };
+        Ok(found.canonicalize().unwrap_or_else(|_| found.clone()))
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .into_owned()
+}
+
+impl ShaderModule {
+    /// The entry points the generated pipeline uses, as indices into `module.entry_points`.
+    pub fn pipeline_entry_points<'a>(
+        &'a self,
+        pipeline: &'a Pipeline,
+    ) -> impl Iterator<Item = (ShaderStage, usize)> + 'a {
+        self.module
+            .entry_points
+            .iter()
+            .enumerate()
+            .filter(move |(_, ep)| match pipeline {
+                Pipeline::Render { vertex, fragment } => {
+                    (ep.stage == ShaderStage::Vertex && ep.name == *vertex)
+                        || (ep.stage == ShaderStage::Fragment
+                            && Some(&ep.name) == fragment.as_ref())
+                }
+                Pipeline::Compute { entry_point, .. } => {
+                    ep.stage == ShaderStage::Compute && ep.name == *entry_point
+                }
+            })
+            .map(|(index, ep)| (ep.stage, index))
+    }
+
+    /// The `stage` entry point called `name`, or `default` when no name was given. A missing
+    /// one is reported at the name, or at the shader path for the default.
+    pub fn entry_point(
+        &self,
+        source: &ShaderSource,
+        stage: ShaderStage,
+        name: Option<&LitStr>,
+        default: &str,
+    ) -> syn::Result<&naga::EntryPoint> {
+        let (name, span) = match name {
+            Some(name) => (name.value(), name.span()),
+            None => (default.to_string(), source.span),
+        };
+        if let Some(entry_point) = self.find_entry_point(stage, &name) {
+            return Ok(entry_point);
+        }
+        let candidates = self
+            .module
+            .entry_points
+            .iter()
+            .filter(|ep| ep.stage == stage)
+            .map(|ep| format!("`{}`", ep.name))
+            .collect::<Vec<_>>();
+        let found = if candidates.is_empty() {
+            format!("it declares no @{} entry points", stage_name(stage))
+        } else {
+            format!("found {}", candidates.join(", "))
+        };
+        Err(syn::Error::new(
+            span,
+            format!(
+                "no `@{} fn {}` entry point in `{}`, {}",
+                stage_name(stage),
+                name,
+                source.path.display(),
+                found
+            ),
+        ))
+    }
+
+    fn find_entry_point(&self, stage: ShaderStage, name: &str) -> Option<&naga::EntryPoint> {
+        self.module
+            .entry_points
+            .iter()
+            .find(|ep| ep.stage == stage && ep.name == name)
+    }
+
+    /// Number of `@location` outputs written by the fragment entry point `name`, if there is
+    /// one.
+    pub fn fragment_outputs(&self, name: &str) -> Option<usize> {
+        let fragment = self.find_entry_point(ShaderStage::Fragment, name)?;
+        Some(fragment.function.result.as_ref().map_or(0, |result| {
+            self.locations(result.binding.as_ref(), result.ty)
+        }))
+    }
+
+    /// The `var<push_constant>` global, if the module declares one.
+    pub fn push_constant(&self) -> Option<(Handle<GlobalVariable>, &GlobalVariable)> {
+        self.module
+            .global_variables
+            .iter()
+            .find(|(_, global)| global.space == AddressSpace::PushConstant)
+    }
+
+    /// Size in bytes of the `var<push_constant>` global, if the module declares one.
+    pub fn push_constant_size(&self) -> Option<u32> {
+        let (_, global) = self.push_constant()?;
+        Some(
+            self.module.types[global.ty]
+                .inner
+                .size(self.module.to_ctx()),
+        )
+    }
+
+    /// Size in bytes of the `var<uniform>` global at `@group(group) @binding(binding)`, if there
+    /// is one.
+    pub fn uniform_size(&self, group: u32, binding: u32) -> Option<u32> {
+        let resource = naga::ResourceBinding { group, binding };
+        let (_, global) = self.module.global_variables.iter().find(|(_, global)| {
+            global.space == AddressSpace::Uniform && global.binding.as_ref() == Some(&resource)
+        })?;
+        Some(
+            self.module.types[global.ty]
+                .inner
+                .size(self.module.to_ctx()),
+        )
+    }
+
+    /// Number of `@location` inputs read by the vertex entry point `name`, if there is one.
+    pub fn vertex_inputs(&self, name: &str) -> Option<usize> {
+        let vertex = self.find_entry_point(ShaderStage::Vertex, name)?;
+        Some(
+            vertex
+                .function
+                .arguments
+                .iter()
+                .map(|argument| self.locations(argument.binding.as_ref(), argument.ty))
+                .sum(),
+        )
+    }
+
+    /// Number of `@location` bindings on an entry point argument or result, counting the members
+    /// of unbound structs.
+    fn locations(&self, binding: Option<&Binding>, ty: naga::Handle<naga::Type>) -> usize {
+        match (binding, &self.module.types[ty].inner) {
+            (Some(Binding::Location { .. }), _) => 1,
+            (None, TypeInner::Struct { members, .. }) => members
+                .iter()
+                .filter(|member| matches!(member.binding, Some(Binding::Location { .. })))
+                .count(),
+            _ => 0,
+        }
+    }
+}
+
+pub fn describe_global(module: &naga::Module, global: &GlobalVariable) -> &'static str {
+    match global.space {
+        AddressSpace::Uniform => "uniform buffer",
+        AddressSpace::Storage { .. } => "storage buffer",
+        AddressSpace::Handle => match module.types[global.ty].inner {
+            TypeInner::Image {
+                class: naga::ImageClass::Storage { .. },
+                ..
+            } => "storage texture",
+            TypeInner::Image { .. } => "texture",
+            TypeInner::Sampler { comparison: false } => "sampler",
+            TypeInner::Sampler { comparison: true } => "comparison sampler",
+            _ => "handle",
+        },
+        _ => "variable",
+    }
+}
+
+pub fn global_name(global: &GlobalVariable) -> &str {
+    global.name.as_deref().unwrap_or("<unnamed>")
+}
+
+pub fn stage_name(stage: ShaderStage) -> &'static str {
+    match stage {
+        ShaderStage::Vertex => "vertex",
+        ShaderStage::Fragment => "fragment",
+        ShaderStage::Compute => "compute",
+    }
+}
+
+fn candidate_paths(path: &LitStr, relative: &Path) -> Vec<PathBuf> {
+    if relative.is_absolute() {
+        return vec![relative.to_path_buf()];
+    }
+
+    let mut candidates = vec![];
+    if let Some(dir) = calling_file_dir(path) {
+        candidates.push(dir.join(relative));
+    }
+    if let Ok(manifest_dir) = std::env::var("CARGO_MANIFEST_DIR") {
+        candidates.push(Path::new(&manifest_dir).join(relative));
+    }
+    candidates
+}
+
+fn calling_file_dir(path: &LitStr) -> Option<PathBuf> {
+    let file = path.span().unwrap().local_file()?;
+    let file = if file.is_absolute() {
+        file
+    } else {
+        std::env::current_dir().ok()?.join(file)
+    };
+    file.parent().map(Path::to_path_buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `files` to a fresh directory and returns the path of the first one.
+    fn write(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bedrock-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        for (path, source) in files {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, source).unwrap();
+        }
+        dir.join(files[0].0).canonicalize().unwrap()
+    }
+
+    fn load(path: &Path) -> syn::Result<ShaderSource> {
+        let path = LitStr::new(path.to_str().unwrap(), Span::call_site());
+        ShaderSource::load(&path, &[])
+    }
+
+    #[test]
+    fn loads_diamond_imports_once() {
+        let path = write(
+            "diamond",
+            &[
+                (
+                    "main.wgsl",
+                    "#import \"a.wgsl\"\n#import \"b.wgsl\"\nfn main_fn() -> f32 { return a() + b(); }\n",
+                ),
+                ("a.wgsl", "#import common::math\nfn a() -> f32 { return one(); }\n"),
+                ("b.wgsl", "#import common::math\nfn b() -> f32 { return one(); }\n"),
+                ("common/math.wgsl", "fn one() -> f32 { return 1.0; }\n"),
+            ],
+        );
+        let source = load(&path).unwrap();
+        let names = source
+            .files
+            .iter()
+            .map(|file| file_name(&file.path))
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["main.wgsl", "a.wgsl", "math.wgsl", "b.wgsl"]);
+        assert_eq!(source.source.matches("fn one()").count(), 1);
+        assert!(!source.source.contains("#import"));
+        // The root file keeps its line numbers.
+        assert!(source.source.starts_with("\n\nfn main_fn()"));
+        source.module().map_err(|err| err.to_string()).unwrap();
+    }
+
+    #[test]
+    fn reports_import_cycles() {
+        let path = write(
+            "cycle",
+            &[
+                ("a.wgsl", "#import \"b.wgsl\"\n"),
+                ("b.wgsl", "\n#import \"a.wgsl\"\n"),
+            ],
+        );
+        let message = load(&path).unwrap_err().to_string();
+        let b = path.with_file_name("b.wgsl");
+        assert_eq!(
+            message,
+            format!("{}:2: import cycle a.wgsl -> b.wgsl -> a.wgsl", b.display())
+        );
+    }
+
+    #[test]
+    fn reports_unknown_imports() {
+        let path = write("unknown", &[("main.wgsl", "#import missing::module\n")]);
+        let message = load(&path).unwrap_err().to_string();
+        assert!(message.starts_with(&format!(
+            "{}:1: imported module `missing::module` not found, tried:",
+            path.display()
+        )));
+    }
+
+    #[test]
+    fn maps_errors_to_imported_files() {
+        let path = write(
+            "mapped",
+            &[
+                (
+                    "main.wgsl",
+                    "#import \"lib.wgsl\"\nfn main_fn() -> f32 { return lib(); }\n",
+                ),
+                (
+                    "lib.wgsl",
+                    "// helpers\n\nfn lib() -> f32 {\n    return undefined_value;\n}\n",
+                ),
+            ],
+        );
+        let source = load(&path).unwrap();
+        let message = source.module().err().unwrap().to_string();
+        let lib = path.with_file_name("lib.wgsl");
+        assert!(
+            message.contains(&format!("{}:4:12", lib.display())),
+            "{}",
+            message
+        );
+        assert!(message.contains("undefined_value"), "{}", message);
+    }
+}
@@ -0,0 +1,731 @@
+use naga::{
+    AddressSpace, ImageClass, ImageDimension, ResourceBinding, ScalarKind, ShaderStage,
+    StorageAccess, TypeInner,
+};
+use wgpu::TextureSampleType;
+
+use crate::{
+    errors::Errors,
+    format::Format,
+    schema::{Entry, EntryVariant, Pipeline, Shader, TextureKind, Visibility},
+    source::{describe_global, global_name, stage_name, ShaderModule},
+};
+
+impl Shader {
+    /// Checks the struct's groups, entries and outputs against the WGSL module, collecting one
+    /// spanned error per mismatch.
+    pub fn validate(&self, shader_module: &ShaderModule) -> syn::Result<()> {
+        let mut errors = Errors::default();
+        let module = &shader_module.module;
+
+        for group in &self.bind_groups {
+            for entry in &group.entries {
+                let resource = ResourceBinding {
+                    group: group.index,
+                    binding: entry.binding,
+                };
+                let Some((handle, global)) = module
+                    .global_variables
+                    .iter()
+                    .find(|(_, global)| global.binding.as_ref() == Some(&resource))
+                else {
+                    errors.push(
+                        entry.span,
+                        format!(
+                            "no WGSL binding declared at @group({}) @binding({}) for `{}`",
+                            resource.group, resource.binding, entry.ident
+                        ),
+                    );
+                    continue;
+                };
+
+                let found = describe_global(module, global);
+                if entry.variant.expected() != found {
+                    errors.push(
+                        entry.span,
+                        format!(
+                            "`{}` is declared as a {} but @group({}) @binding({}) `{}` is a {}",
+                            entry.ident,
+                            entry.variant.expected(),
+                            resource.group,
+                            resource.binding,
+                            global_name(global),
+                            found
+                        ),
+                    );
+                }
+
+                if let (
+                    EntryVariant::Texture { kind, format, .. },
+                    TypeInner::Image {
+                        dim,
+                        arrayed,
+                        class,
+                    },
+                ) = (&entry.variant, &module.types[global.ty].inner)
+                {
+                    let found = wgsl_dimension(*dim, *arrayed);
+                    if kind.wgsl_dimension() != found {
+                        errors.push(
+                            entry.span,
+                            format!(
+                                "`{}` is a {} but the WGSL texture `{}` is a {}",
+                                entry.ident,
+                                kind.wgsl_dimension(),
+                                global_name(global),
+                                found
+                            ),
+                        );
+                    }
+
+                    let expected = match format.as_ref().and_then(Format::sample_type) {
+                        Some(TextureSampleType::Depth) => "depth",
+                        Some(TextureSampleType::Uint) => "u32",
+                        Some(TextureSampleType::Sint) => "i32",
+                        _ => "f32",
+                    };
+                    let found = match class {
+                        ImageClass::Sampled { kind, .. } => match kind {
+                            ScalarKind::Uint => "u32",
+                            ScalarKind::Sint => "i32",
+                            _ => "f32",
+                        },
+                        ImageClass::Depth { .. } => "depth",
+                        ImageClass::Storage { .. } => expected,
+                    };
+                    if expected != found {
+                        errors.push(
+                            entry.span,
+                            format!(
+                                "`{}` holds {} texels ({}) but the WGSL texture `{}` reads {}",
+                                entry.ident,
+                                expected,
+                                format
+                                    .as_ref()
+                                    .map_or("the swapchain format".to_string(), |format| {
+                                        format!("format `{}`", format.ident)
+                                    }),
+                                global_name(global),
+                                found
+                            ),
+                        );
+                    }
+                }
+
+                if let (
+                    EntryVariant::StorageTexture { format, access, .. },
+                    TypeInner::Image {
+                        dim,
+                        arrayed,
+                        class:
+                            ImageClass::Storage {
+                                format: wgsl_format,
+                                access: wgsl_access,
+                            },
+                    },
+                ) = (&entry.variant, &module.types[global.ty].inner)
+                {
+                    // #[storage_texture] layouts are always 2D.
+                    let found = wgsl_dimension(*dim, *arrayed);
+                    if found != wgsl_dimension(ImageDimension::D2, false) {
+                        errors.push(
+                            entry.span,
+                            format!(
+                                "`{}` is a texture_storage_2d but the WGSL storage texture `{}` is a {}",
+                                entry.ident,
+                                global_name(global),
+                                found.replacen("texture_", "texture_storage_", 1)
+                            ),
+                        );
+                    }
+                    // naga names its storage formats after the matching `wgpu::TextureFormat`.
+                    let wgsl_format = format!("{:?}", wgsl_format);
+                    if format.ident != wgsl_format {
+                        errors.push(
+                            format.ident.span(),
+                            format!(
+                                "`{}` has format `{}` but the WGSL storage texture `{}` is declared with `{}`",
+                                entry.ident,
+                                format.ident,
+                                global_name(global),
+                                wgsl_format.to_lowercase()
+                            ),
+                        );
+                    }
+                    let (wgsl_access, option) = match (
+                        wgsl_access.contains(StorageAccess::LOAD),
+                        wgsl_access.contains(StorageAccess::STORE),
+                    ) {
+                        (true, true) => ("ReadWrite", "read_write"),
+                        (true, false) => ("ReadOnly", "read_only"),
+                        _ => ("WriteOnly", "write_only"),
+                    };
+                    if access != wgsl_access {
+                        errors.push(
+                            access.span(),
+                            format!(
+                                "`{}` doesn't match the access of the WGSL storage texture `{}`, use `access = {}`",
+                                entry.ident,
+                                global_name(global),
+                                option
+                            ),
+                        );
+                    }
+                    if access != "ReadOnly" && entry.visibility.vertex {
+                        errors.push(
+                            entry.visibility.span,
+                            format!(
+                                "`{}` is a writable storage texture, which vertex shaders can't bind, but it is visible to the vertex stage",
+                                entry.ident
+                            ),
+                        );
+                    }
+                }
+
+                if let (EntryVariant::Storage { read_only }, AddressSpace::Storage { access }) =
+                    (&entry.variant, global.space)
+                {
+                    let wgsl_read_only = !access.contains(StorageAccess::STORE);
+                    if *read_only != wgsl_read_only {
+                        errors.push(
+                            entry.span,
+                            format!(
+                                "`{}` is declared `{}` but the WGSL global `{}` is `var<storage, {}>`",
+                                entry.ident,
+                                if *read_only { "read" } else { "read_write" },
+                                global_name(global),
+                                if wgsl_read_only { "read" } else { "read_write" },
+                            ),
+                        );
+                    }
+                }
+
+                if let EntryVariant::Storage { read_only: false } = entry.variant {
+                    if entry.visibility.vertex {
+                        errors.push(
+                            entry.visibility.span,
+                            format!(
+                                "`{}` is a read_write storage buffer, which vertex shaders can't bind, but it is visible to the vertex stage",
+                                entry.ident
+                            ),
+                        );
+                    }
+                }
+
+                for (stage, entry_point) in shader_module.pipeline_entry_points(&self.pipeline) {
+                    let used = !shader_module.info.get_entry_point(entry_point)[handle].is_empty();
+                    if used && !entry.visibility.visible_to(stage) {
+                        errors.push(
+                            entry.visibility.span,
+                            format!(
+                                "`{}` is used by the {} entry point `{}` but is not visible to it",
+                                entry.ident,
+                                stage_name(stage),
+                                module.entry_points[entry_point].name,
+                            ),
+                        );
+                    }
+                }
+            }
+        }
+
+        for (stage, entry_point) in shader_module.pipeline_entry_points(&self.pipeline) {
+            let info = shader_module.info.get_entry_point(entry_point);
+            for (handle, global) in module.global_variables.iter() {
+                let Some(resource) = &global.binding else {
+                    continue;
+                };
+                if self.entry_at(resource).is_none() && !info[handle].is_empty() {
+                    errors.push(
+                        self.span,
+                        format!(
+                            "@group({}) @binding({}) `{}` is used by the {} entry point `{}` but has no matching field",
+                            resource.group,
+                            resource.binding,
+                            global_name(global),
+                            stage_name(stage),
+                            module.entry_points[entry_point].name,
+                        ),
+                    );
+                }
+            }
+        }
+
+        match (&self.push_constants, shader_module.push_constant()) {
+            (Some(push_constants), None) => errors.push(
+                push_constants.span,
+                "no `var<push_constant>` declared in the WGSL module for #[push_constants]"
+                    .to_string(),
+            ),
+            (push_constants, Some((handle, global))) => {
+                for (stage, entry_point) in shader_module.pipeline_entry_points(&self.pipeline) {
+                    if shader_module.info.get_entry_point(entry_point)[handle].is_empty() {
+                        continue;
+                    }
+                    let stage_flag = stage_name(stage).to_uppercase();
+                    match push_constants {
+                        None => errors.push(
+                            self.span,
+                            format!(
+                                "`var<push_constant> {}` is used by the {} entry point `{}` but has no matching #[push_constants] field",
+                                global_name(global),
+                                stage_name(stage),
+                                module.entry_points[entry_point].name,
+                            ),
+                        ),
+                        Some(push_constants)
+                            if !push_constants.stages.iter().any(|s| *s == stage_flag) =>
+                        {
+                            errors.push(
+                                push_constants.span,
+                                format!(
+                                    "`var<push_constant> {}` is used by the {} entry point `{}`, add `{}` to `stages`",
+                                    global_name(global),
+                                    stage_name(stage),
+                                    module.entry_points[entry_point].name,
+                                    stage_name(stage),
+                                ),
+                            )
+                        }
+                        Some(_) => {}
+                    }
+                }
+            }
+            (None, None) => {}
+        }
+
+        for (stage, entry_point) in shader_module.pipeline_entry_points(&self.pipeline) {
+            let info = shader_module.info.get_entry_point(entry_point);
+            for key in &info.sampling_set {
+                let (texture, sampler) = (
+                    &module.global_variables[key.image],
+                    &module.global_variables[key.sampler],
+                );
+                let (Some(texture_entry), Some(sampler_entry)) = (
+                    texture.binding.as_ref().and_then(|b| self.entry_at(b)),
+                    sampler.binding.as_ref().and_then(|b| self.entry_at(b)),
+                ) else {
+                    continue;
+                };
+                if let (
+                    EntryVariant::Texture {
+                        filterable: false, ..
+                    },
+                    EntryVariant::Sampler {
+                        filter,
+                        compare: None,
+                        ..
+                    },
+                ) = (&texture_entry.variant, &sampler_entry.variant)
+                {
+                    if filter == "Linear" {
+                        errors.push(
+                            texture_entry.span,
+                            format!(
+                                "`{}` is sampled with the filtering sampler `{}` in the {} entry point `{}`, mark the texture `filterable`",
+                                texture_entry.ident,
+                                sampler_entry.ident,
+                                stage_name(stage),
+                                module.entry_points[entry_point].name,
+                            ),
+                        );
+                    }
+                }
+            }
+        }
+
+        if let Pipeline::Render { vertex, .. } = &self.pipeline {
+            match (self.vertex_buffers.first(), shader_module.vertex_inputs(vertex)) {
+                (None, Some(inputs)) if inputs > 0 => errors.push(
+                    self.span,
+                    format!(
+                        "vertex entry point `{}` reads {} @location input(s) but no #[vertex] or #[instance] buffer is declared",
+                        vertex, inputs
+                    ),
+                ),
+                (Some(buffer), Some(0)) => errors.push(
+                    buffer.span,
+                    format!(
+                        "vertex entry point `{}` reads no @location inputs, remove the vertex buffer",
+                        vertex
+                    ),
+                ),
+                _ => {}
+            }
+        }
+
+        if let (Some(strip_index_format), Some(index)) = (
+            self.primitive.strip_index_format,
+            self.index_buffers.first(),
+        ) {
+            if strip_index_format != index.format() {
+                errors.push(
+                    index.span,
+                    format!(
+                        "the #[index] buffer holds `{}` but `strip_index_format = {}`",
+                        index.element,
+                        match strip_index_format {
+                            wgpu::IndexFormat::Uint16 => "u16",
+                            wgpu::IndexFormat::Uint32 => "u32",
+                        }
+                    ),
+                );
+            }
+        }
+
+        if let Pipeline::Render { fragment: None, .. } = &self.pipeline {
+            if let Some(output) = self.outputs.first() {
+                errors.push(
+                    output.span,
+                    "`vertex_only` pipelines have no fragment stage to write #[output] fields"
+                        .to_string(),
+                );
+            }
+            if self.depth.is_none() {
+                errors.push(
+                    self.span,
+                    "`vertex_only` pipelines need a #[depth] attachment to render into".to_string(),
+                );
+            }
+        }
+
+        if let Pipeline::Render {
+            fragment: Some(fragment),
+            ..
+        } = &self.pipeline
+        {
+            let locations = shader_module.fragment_outputs(fragment).unwrap_or(0);
+            if locations != self.outputs.len() {
+                let span = self
+                    .outputs
+                    .get(locations)
+                    .or(self.outputs.last())
+                    .map_or(self.span, |output| output.span);
+                errors.push(
+                    span,
+                    format!(
+                        "fragment entry point `{}` writes {} @location output(s) but {} #[output] field(s) are declared",
+                        fragment,
+                        locations,
+                        self.outputs.len()
+                    ),
+                );
+            }
+        }
+
+        errors.finish()
+    }
+
+    fn entry_at(&self, resource: &ResourceBinding) -> Option<&Entry> {
+        self.bind_groups
+            .iter()
+            .find(|group| group.index == resource.group)?
+            .entries
+            .iter()
+            .find(|entry| entry.binding == resource.binding)
+    }
+}
+
+impl TextureKind {
+    fn wgsl_dimension(self) -> &'static str {
+        match self {
+            TextureKind::D2 => wgsl_dimension(ImageDimension::D2, false),
+            TextureKind::D2Array => wgsl_dimension(ImageDimension::D2, true),
+            TextureKind::D3 => wgsl_dimension(ImageDimension::D3, false),
+            TextureKind::Cube => wgsl_dimension(ImageDimension::Cube, false),
+        }
+    }
+}
+
+fn wgsl_dimension(dim: ImageDimension, arrayed: bool) -> &'static str {
+    match (dim, arrayed) {
+        (ImageDimension::D1, _) => "texture_1d",
+        (ImageDimension::D2, false) => "texture_2d",
+        (ImageDimension::D2, true) => "texture_2d_array",
+        (ImageDimension::D3, _) => "texture_3d",
+        (ImageDimension::Cube, false) => "texture_cube",
+        (ImageDimension::Cube, true) => "texture_cube_array",
+    }
+}
+
+impl Visibility {
+    fn visible_to(&self, stage: ShaderStage) -> bool {
+        match stage {
+            ShaderStage::Vertex => self.vertex,
+            ShaderStage::Fragment => self.fragment,
+            ShaderStage::Compute => self.compute,
+        }
+    }
+}
+
+impl EntryVariant {
+    /// The kind of WGSL global this entry must bind to, as reported by `describe_global`.
+    fn expected(&self) -> &'static str {
+        match self {
+            EntryVariant::Texture { .. } => "texture",
+            EntryVariant::StorageTexture { .. } => "storage texture",
+            EntryVariant::Uniform { .. } => "uniform buffer",
+            EntryVariant::Storage { .. } => "storage buffer",
+            EntryVariant::Sampler { compare: None, .. } => "sampler",
+            EntryVariant::Sampler {
+                compare: Some(_), ..
+            } => "comparison sampler",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use proc_macro2::Span;
+
+    use super::*;
+    use crate::{
+        parser::ShaderParser,
+        source::{ShaderSource, SourceFile},
+    };
+
+    /// Validates a render shader struct with `fields` against `wgsl`, whose entry points are
+    /// `vs_main` and `fs_main`, returning every error message.
+    fn validate(wgsl: &str, fields: &str) -> Vec<String> {
+        let path = PathBuf::from("test.wgsl");
+        let source = ShaderSource {
+            path: path.clone(),
+            source: wgsl.to_string(),
+            files: vec![SourceFile {
+                path,
+                source: wgsl.to_string(),
+            }],
+            span: Span::call_site(),
+        };
+        let module = source.module().map_err(|err| err.to_string()).unwrap();
+        let input =
+            syn::parse_str::<syn::DeriveInput>(&format!("struct Test {{ {} }}", fields)).unwrap();
+        let syn::Data::Struct(data) = input.data else {
+            unreachable!()
+        };
+        let mut parser = ShaderParser::new();
+        parser.process_fields(&data.fields).unwrap();
+        let shader = Shader {
+            source,
+            pipeline: Pipeline::Render {
+                vertex: "vs_main".to_string(),
+                fragment: Some("fs_main".to_string()),
+            },
+            vertex_buffers: parser.vertex_buffers,
+            index_buffers: parser.index_buffers,
+            bind_groups: parser.bind_groups,
+            outputs: parser.outputs,
+            depth: None,
+            push_constants: parser.push_constants,
+            samples: 1,
+            primitive: Default::default(),
+            generated_types: vec![],
+            span: Span::call_site(),
+        };
+        match shader.validate(&module) {
+            Ok(()) => vec![],
+            Err(errors) => errors.into_iter().map(|error| error.to_string()).collect(),
+        }
+    }
+
+    const VERTEX: &str = "
+@vertex
+fn vs_main() -> @builtin(position) vec4<f32> {
+    return vec4<f32>(0.0);
+}
+";
+
+    fn wgsl(globals: &str, fragment_body: &str) -> String {
+        format!(
+            "{}{}
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {{
+    {}
+}}
+",
+            globals, VERTEX, fragment_body
+        )
+    }
+
+    const TEXTURE_FIELDS: &str = "
+        #[group(fragment)]
+        #[texture2d(width = 4, height = 4)]
+        tex: Tex,
+        #[output]
+        out: Out,
+    ";
+
+    #[test]
+    fn accepts_a_matching_struct() {
+        let wgsl = wgsl(
+            "
+@group(0) @binding(0) var tex: texture_2d<f32>;
+@group(0) @binding(1) var<uniform> scale: f32;
+",
+            "return textureLoad(tex, vec2<i32>(0), 0) * scale;",
+        );
+        let fields = "
+            #[group(fragment)]
+            #[texture2d(width = 4, height = 4)]
+            tex: Tex,
+            #[uniform]
+            scale: Scale,
+            #[output]
+            out: Out,
+        ";
+        assert_eq!(validate(&wgsl, fields), Vec::<String>::new());
+    }
+
+    #[test]
+    fn reports_missing_bindings() {
+        let wgsl = wgsl("", "return vec4<f32>(0.0);");
+        assert_eq!(
+            validate(&wgsl, TEXTURE_FIELDS),
+            ["no WGSL binding declared at @group(0) @binding(0) for `tex`"]
+        );
+    }
+
+    #[test]
+    fn reports_unmatched_wgsl_bindings() {
+        let wgsl = wgsl(
+            "
+@group(0) @binding(0) var tex: texture_2d<f32>;
+@group(0) @binding(1) var<uniform> scale: f32;
+",
+            "return textureLoad(tex, vec2<i32>(0), 0) * scale;",
+        );
+        assert_eq!(
+            validate(&wgsl, TEXTURE_FIELDS),
+            ["@group(0) @binding(1) `scale` is used by the fragment entry point `fs_main` but has no matching field"]
+        );
+    }
+
+    #[test]
+    fn reports_wrong_binding_kinds() {
+        let wgsl = wgsl(
+            "@group(0) @binding(0) var<uniform> tex: vec4<f32>;",
+            "return tex;",
+        );
+        assert_eq!(
+            validate(&wgsl, TEXTURE_FIELDS),
+            ["`tex` is declared as a texture but @group(0) @binding(0) `tex` is a uniform buffer"]
+        );
+    }
+
+    #[test]
+    fn reports_texture_dimension_mismatches() {
+        let wgsl = wgsl(
+            "@group(0) @binding(0) var tex: texture_3d<f32>;",
+            "return textureLoad(tex, vec3<i32>(0), 0);",
+        );
+        assert_eq!(
+            validate(&wgsl, TEXTURE_FIELDS),
+            ["`tex` is a texture_2d but the WGSL texture `tex` is a texture_3d"]
+        );
+    }
+
+    #[test]
+    fn reports_sample_type_mismatches() {
+        let wgsl = wgsl(
+            "@group(0) @binding(0) var tex: texture_2d<f32>;",
+            "return textureLoad(tex, vec2<i32>(0), 0);",
+        );
+        let fields = "
+            #[group(fragment)]
+            #[texture2d(width = 4, height = 4, format = Rgba8Uint)]
+            tex: Tex,
+            #[output]
+            out: Out,
+        ";
+        assert_eq!(
+            validate(&wgsl, fields),
+            ["`tex` holds u32 texels (format `Rgba8Uint`) but the WGSL texture `tex` reads f32"]
+        );
+    }
+
+    #[test]
+    fn reports_storage_texture_dimension_mismatches() {
+        let fields = "
+            #[group(fragment)]
+            #[storage_texture(width = 4, height = 4, format = Rgba8Unorm, access = write_only)]
+            tex: Tex,
+            #[output]
+            out: Out,
+        ";
+        let wgsl_3d = wgsl(
+            "@group(0) @binding(0) var tex: texture_storage_3d<rgba8unorm, write>;",
+            "textureStore(tex, vec3<i32>(0), vec4<f32>(0.0));\n    return vec4<f32>(0.0);",
+        );
+        assert_eq!(
+            validate(&wgsl_3d, fields),
+            ["`tex` is a texture_storage_2d but the WGSL storage texture `tex` is a texture_storage_3d"]
+        );
+        let wgsl_array = wgsl(
+            "@group(0) @binding(0) var tex: texture_storage_2d_array<rgba8unorm, write>;",
+            "textureStore(tex, vec2<i32>(0), 0, vec4<f32>(0.0));\n    return vec4<f32>(0.0);",
+        );
+        assert_eq!(
+            validate(&wgsl_array, fields),
+            ["`tex` is a texture_storage_2d but the WGSL storage texture `tex` is a texture_storage_2d_array"]
+        );
+        let wgsl_2d = wgsl(
+            "@group(0) @binding(0) var tex: texture_storage_2d<rgba8unorm, write>;",
+            "textureStore(tex, vec2<i32>(0), vec4<f32>(0.0));\n    return vec4<f32>(0.0);",
+        );
+        assert_eq!(validate(&wgsl_2d, fields), Vec::<String>::new());
+    }
+
+    #[test]
+    fn reports_bindings_used_by_invisible_stages() {
+        let wgsl = wgsl(
+            "@group(0) @binding(0) var tex: texture_2d<f32>;",
+            "return textureLoad(tex, vec2<i32>(0), 0);",
+        );
+        let fields = "
+            #[group(vertex)]
+            #[texture2d(width = 4, height = 4)]
+            tex: Tex,
+            #[output]
+            out: Out,
+        ";
+        assert_eq!(
+            validate(&wgsl, fields),
+            ["`tex` is used by the fragment entry point `fs_main` but is not visible to it"]
+        );
+    }
+
+    #[test]
+    fn reports_filtering_samplers_on_unfilterable_textures() {
+        let wgsl = wgsl(
+            "
+@group(0) @binding(0) var tex: texture_2d<f32>;
+@group(0) @binding(1) var samp: sampler;
+",
+            "return textureSample(tex, samp, vec2<f32>(0.0));",
+        );
+        let fields = "
+            #[group(fragment)]
+            #[texture2d(width = 4, height = 4)]
+            tex: Tex,
+            #[sampler(filter = linear)]
+            samp: Samp,
+            #[output]
+            out: Out,
+        ";
+        assert_eq!(
+            validate(&wgsl, fields),
+            ["`tex` is sampled with the filtering sampler `samp` in the fragment entry point `fs_main`, mark the texture `filterable`"]
+        );
+    }
+
+    #[test]
+    fn reports_output_count_mismatches() {
+        let wgsl = wgsl("", "return vec4<f32>(0.0);");
+        assert_eq!(
+            validate(&wgsl, "#[output] a: A, #[output] b: B,"),
+            ["fragment entry point `fs_main` writes 1 @location output(s) but 2 #[output] field(s) are declared"]
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{spanned::Spanned, DeriveInput, Expr, ExprLit, Ident, Lit, Meta, NestedMeta, Type};
+
+/// Implements `VertexProvider` for a `#[repr(C)]` struct, one vertex attribute per field at
+/// consecutive shader locations.
+pub fn expand_derive_vertex(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let ident = &input.ident;
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            input.generics.span(),
+            "#[derive(Vertex)] does not support generic structs",
+        ));
+    }
+    if !is_repr_c(&input)? {
+        return Err(syn::Error::new(
+            ident.span(),
+            "#[derive(Vertex)] needs `#[repr(C)]` so field offsets match the buffer layout",
+        ));
+    }
+    let fields = match &input.data {
+        syn::Data::Struct(syn::DataStruct {
+            fields: syn::Fields::Named(fields),
+            ..
+        }) => &fields.named,
+        _ => {
+            return Err(syn::Error::new(
+                ident.span(),
+                "#[derive(Vertex)] can only be applied to structs with named fields",
+            ))
+        }
+    };
+
+    let attributes = fields
+        .iter()
+        .enumerate()
+        .map(|(location, field)| {
+            let format = vertex_format(&field.ty)?;
+            let field_ident = &field.ident;
+            let location = location as u32;
+            Ok(quote! {
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::#format,
+                    offset: std::mem::offset_of!(#ident, #field_ident) as u64,
+                    shader_location: #location,
+                },
+            })
+        })
+        .collect::<syn::Result<TokenStream2>>()?;
+
+    Ok(quote! {
+        impl VertexProvider for #ident {
+            const ATTRIBUTES: &'static [wgpu::VertexAttribute] = &[#attributes];
+        }
+    })
+}
+
+fn is_repr_c(input: &DeriveInput) -> syn::Result<bool> {
+    for attr in &input.attrs {
+        if !attr.path.is_ident("repr") {
+            continue;
+        }
+        // Other representation hints may come along, as in `#[repr(C, align(16))]`.
+        if let Meta::List(list) = attr.parse_meta()? {
+            let is_c = |repr: &NestedMeta| matches!(repr, NestedMeta::Meta(Meta::Path(path)) if path.is_ident("C"));
+            if list.nested.iter().any(is_c) {
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
+/// The `wgpu::VertexFormat` variant for a field type: `f32`, `u32` and `i32` scalars, arrays of
+/// two to four of them, and the matching glam vectors.
+fn vertex_format(ty: &Type) -> syn::Result<Ident> {
+    let (scalar, count) = match ty {
+        Type::Array(array) => {
+            let Expr::Lit(ExprLit {
+                lit: Lit::Int(len), ..
+            }) = &array.len
+            else {
+                return Err(unsupported(ty));
+            };
+            (type_name(&array.elem), len.base10_parse::<usize>()?)
+        }
+        ty => {
+            let name = type_name(ty);
+            match name.as_deref().and_then(glam_vector) {
+                Some((scalar, count)) => (Some(scalar.to_string()), count),
+                None => (name, 1),
+            }
+        }
+    };
+    let base = match scalar.as_deref() {
+        Some("f32") => "Float32",
+        Some("u32") => "Uint32",
+        Some("i32") => "Sint32",
+        _ => return Err(unsupported(ty)),
+    };
+    match count {
+        1 => Ok(format_ident!("{}", base)),
+        2..=4 => Ok(format_ident!("{}x{}", base, count)),
+        _ => Err(unsupported(ty)),
+    }
+}
+
+fn type_name(ty: &Type) -> Option<String> {
+    match ty {
+        Type::Path(path) if path.qself.is_none() => {
+            Some(path.path.segments.last()?.ident.to_string())
+        }
+        _ => None,
+    }
+}
+
+fn glam_vector(name: &str) -> Option<(&'static str, usize)> {
+    Some(match name {
+        "Vec2" => ("f32", 2),
+        "Vec3" => ("f32", 3),
+        "Vec4" => ("f32", 4),
+        "UVec2" => ("u32", 2),
+        "UVec3" => ("u32", 3),
+        "UVec4" => ("u32", 4),
+        "IVec2" => ("i32", 2),
+        "IVec3" => ("i32", 3),
+        "IVec4" => ("i32", 4),
+        _ => return None,
+    })
+}
+
+fn unsupported(ty: &Type) -> syn::Error {
+    syn::Error::new(
+        ty.span(),
+        "unsupported vertex attribute type, expected `f32`, `u32`, `i32`, an array of 2 to 4 of them, or a glam `Vec2`/`Vec3`/`Vec4`, `UVec*` or `IVec*`",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn derive(source: &str) -> syn::Result<TokenStream2> {
+        expand_derive_vertex(syn::parse_str(source).unwrap())
+    }
+
+    fn error(source: &str) -> String {
+        derive(source).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn accepts_repr_c_with_other_hints() {
+        derive("#[repr(C)] struct V { position: [f32; 3] }").unwrap();
+        derive("#[repr(C, align(16))] struct V { position: [f32; 3], id: u32 }").unwrap();
+        derive("#[repr(align(16))] #[repr(C)] struct V { position: [f32; 3] }").unwrap();
+    }
+
+    #[test]
+    fn rejects_structs_without_repr_c() {
+        let message =
+            "#[derive(Vertex)] needs `#[repr(C)]` so field offsets match the buffer layout";
+        assert_eq!(error("struct V { position: [f32; 3] }"), message);
+        assert_eq!(
+            error("#[repr(align(16))] struct V { position: [f32; 3] }"),
+            message
+        );
+        assert_eq!(
+            error("#[repr(transparent)] struct V { position: [f32; 3] }"),
+            message
+        );
+    }
+}
@@ -1,11 +1,19 @@
-use bedrock_lib::{driver::Driver, runtime::Runtime, TextureProvider, UniformProvider};
+use bedrock_lib::{
+    driver::Driver, runtime::Runtime, SurfaceView, TextureProvider, UniformProvider,
+};
 use bedrock_shader::shader;
+use encase::ShaderType;
 use rand::Rng;
 use wgpu::util::DeviceExt;
 use winit::event::{Event, WindowEvent};
 
 struct InputTexture {}
-struct Flags {}
+
+#[derive(ShaderType)]
+struct Flags {
+    frame: u32,
+    time: f32,
+}
 
 #[shader("shader.wgsl")]
 struct MainShader {
@@ -13,7 +21,7 @@ struct MainShader {
     #[texture2d(width = 1920, height = 1200)]
     input_texture: InputTexture,
 
-    #[uniform(size = 8)]
+    #[uniform]
     flags: Flags,
 
     #[output]
@@ -34,8 +42,8 @@ async fn async_main(runtime: Runtime) {
 
     let flags = Flags::uniform(&driver.device);
 
-    let mut ping_texture = InputTexture::texture(&driver.device);
-    let mut pong_texture = InputTexture::texture(&driver.device);
+    let ping_texture = InputTexture::texture(&driver.device);
+    let pong_texture = InputTexture::texture(&driver.device);
 
     let ping_group = main_shader::group_0(&driver.device, &ping_texture, &flags);
     let pong_group = main_shader::group_0(&driver.device, &pong_texture, &flags);
@@ -86,6 +94,9 @@ async fn async_main(runtime: Runtime) {
 
     /////////////
 
+    let start = std::time::Instant::now();
+    let mut frame = 0;
+
     runtime
         .event_loop
         .run(move |event, target| {
@@ -96,20 +107,28 @@ async fn async_main(runtime: Runtime) {
             {
                 match event {
                     WindowEvent::RedrawRequested => {
+                        flags.write(
+                            &driver.queue,
+                            &Flags {
+                                frame,
+                                time: start.elapsed().as_secs_f32(),
+                            },
+                        );
+                        frame += 1;
+
                         let frame = driver
                             .surface
                             .get_current_texture()
                             .expect("Failed to acquire next swap chain texture");
-                        let swap_chain_view = frame
-                            .texture
-                            .create_view(&wgpu::TextureViewDescriptor::default());
+                        let swap_chain_view = SurfaceView::new(&frame);
                         let encoder = main_shader::draw_encoder(
                             &driver.device,
                             0..6,
                             0..1,
                             &ping_group,
-                            &pong_texture.view,
+                            &pong_texture,
                             &swap_chain_view,
+                            None,
                         );
                         driver.queue.submit(Some(encoder.finish()));
 
@@ -118,8 +137,9 @@ async fn async_main(runtime: Runtime) {
                             0..6,
                             0..1,
                             &pong_group,
-                            &ping_texture.view,
+                            &ping_texture,
                             &swap_chain_view,
+                            None,
                         );
                         driver.queue.submit(Some(encoder.finish()));
 
@@ -15,9 +15,14 @@ fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> @builtin(position) ve
 @binding(0)
 var r_color: texture_2d<f32>;
 
+struct Flags {
+    frame: u32,
+    time: f32,
+}
+
 @group(0)
 @binding(1)
-var<uniform> ffff: vec2<f32>;
+var<uniform> flags: Flags;
 
 struct FragmentOutput {
   @location(0) color0: vec4<f32>,